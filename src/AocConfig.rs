@@ -1,18 +1,80 @@
 #![allow(non_snake_case, non_camel_case_types)]
 use std::{
-    collections::HashMap,
-    env, fs, io,
+    collections::{BTreeMap, HashMap},
+    env,
+    ffi::OsString,
+    fs,
     path::{Path, PathBuf},
 };
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::rdb::Rdb;
+use crate::{
+    error::{Context, Error, Result},
+    rdb::Rdb,
+    utils::skip_in_dry_run,
+};
+
+/// Environment variable with the romfs paths, used when `--romfs` isn't given.
+/// Several roots are separated like in `PATH`.
+pub const ROMFS_ENV: &str = "AOC_ROMFS";
+
+/// Where the romfs path can come from besides the config file
+#[derive(Debug, Clone)]
+pub struct ConfigOptions {
+    /// Base romfs then the update and DLC layers, takes precedence over `AOC_ROMFS` and the config file
+    pub romfs: Vec<PathBuf>,
+    /// Ask for the romfs with a folder picker and show errors in message boxes
+    pub gui: bool,
+    /// Print the writes of the config file and the hash cache instead of doing them
+    pub dry_run: bool,
+}
+
+// Not derived: `gui` is on by default when the feature is enabled
+#[allow(clippy::derivable_impls)]
+impl Default for ConfigOptions {
+    fn default() -> Self {
+        Self {
+            romfs: Vec::new(),
+            gui: cfg!(feature = "gui"),
+            dry_run: false,
+        }
+    }
+}
+
+impl ConfigOptions {
+    /// Romfs roots from `--romfs` or `AOC_ROMFS`, which override the config file
+    pub fn romfs_override(&self) -> Option<Vec<PathBuf>> {
+        self.romfs_override_from(env::var_os(ROMFS_ENV))
+    }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+    /// Same as `romfs_override`, with the value of `AOC_ROMFS` given by the caller
+    pub fn romfs_override_from(&self, romfs_env: Option<OsString>) -> Option<Vec<PathBuf>> {
+        if !self.romfs.is_empty() {
+            return Some(self.romfs.clone());
+        }
+        let roots: Vec<PathBuf> = env::split_paths(&romfs_env?)
+            .filter(|x| !x.as_os_str().is_empty())
+            .collect();
+        (!roots.is_empty()).then_some(roots)
+    }
+}
+
+/// Contents of `AOC_hashes.json`, rebuilt when the romfs roots change
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct HashesCache {
+    roots: Vec<String>,
+    hashes: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct AocConfig {
+    /// Base game romfs
     pub romfs: String,
+    /// Update and DLC romfs roots, each one shadowing the RDBs and files of the previous ones
+    #[serde(default)]
+    pub layers: Vec<String>,
     #[serde(skip)]
     pub hashes: HashMap<String, Vec<String>>,
     #[serde(skip)]
@@ -21,50 +83,67 @@ pub struct AocConfig {
     pub config_path: String,
     #[serde(skip)]
     pub hashes_json_path: String,
-}
-
-impl Default for AocConfig {
-    fn default() -> Self {
-        Self {
-            romfs: String::new(),
-            hashes: HashMap::new(),
-            hashes_rev: HashMap::new(),
-            config_path: String::new(),
-            hashes_json_path: String::new(),
-        }
-    }
+    #[serde(skip)]
+    pub dry_run: bool,
 }
 
 impl AocConfig {
-    pub fn safe_new() -> io::Result<AocConfig> {
-        match Self::new() {
+    pub fn safe_new(options: &ConfigOptions) -> Result<AocConfig> {
+        match Self::new(options) {
             Ok(conf) => Ok(conf),
             Err(err) => {
-                rfd::MessageDialog::new()
-                    .set_buttons(rfd::MessageButtons::Ok)
-                    .set_title("Error")
-                    .set_description(&format!("{}", err))
-                    .show();
+                #[cfg(feature = "gui")]
+                if options.gui {
+                    rfd::MessageDialog::new()
+                        .set_buttons(rfd::MessageButtons::Ok)
+                        .set_title("Error")
+                        .set_description(format!("{}", err))
+                        .show();
+                }
                 Err(err)
             }
         }
     }
-    #[allow(dead_code)]
-    pub fn to_json(&self) -> io::Result<serde_json::Value> {
+    pub fn to_json(&self) -> Result<serde_json::Value> {
         Ok(json!({
             "romfs": self.romfs,
+            "layers": self.layers,
         }))
     }
 
-    pub fn to_react_json(&self) -> io::Result<serde_json::Value> {
+    pub fn to_react_json(&self) -> Result<serde_json::Value> {
         Ok(json!({
             "romfs": self.romfs,
+            "layers": self.layers,
         }))
     }
 
-    pub fn new() -> io::Result<AocConfig> {
-        let mut conf = Self::default();
-        conf.get_config_path()?;
+    /// Romfs from `--romfs` or `AOC_ROMFS`, then the config file, then the folder picker if allowed
+    pub fn new(options: &ConfigOptions) -> Result<AocConfig> {
+        Self::new_in(options, &config_dir()?, env::var_os(ROMFS_ENV))
+    }
+
+    /// Same as `new`, with the config directory and the value of `AOC_ROMFS` given by the caller
+    pub fn new_in(options: &ConfigOptions, config_dir: &Path, romfs_env: Option<OsString>) -> Result<AocConfig> {
+        let mut conf = Self {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+        conf.get_config_path(config_dir)?;
+
+        // Overrides are used as is, without falling back to the config file or saving them
+        if let Some(roots) = options.romfs_override_from(romfs_env) {
+            let mut roots = roots.iter().map(|x| to_slash_string(x));
+            let romfs = roots.next().unwrap_or_default();
+            if !Self::check_if_romfs_valid(&romfs) {
+                return Err(Error::RomfsNotFound(format!("CharacterEditor.rdb not found in '{}'", romfs)));
+            }
+            conf.romfs = romfs;
+            conf.layers = roots.collect();
+            conf.check_layers()?;
+            conf.get_hashes(false)?;
+            return Ok(conf);
+        }
 
         let mut err_str = String::new();
 
@@ -76,36 +155,30 @@ impl AocConfig {
             return Ok(conf);
         }
 
-        if let Err(err) = conf.update_from_input() {
-            log_error(&mut err_str, err);
-        }
+        #[cfg(feature = "gui")]
+        if options.gui {
+            if let Err(err) = conf.update_from_input() {
+                log_error(&mut err_str, err);
+            }
 
-        if conf.try_save_config()? {
-            return Ok(conf);
+            if conf.try_save_config()? {
+                return Ok(conf);
+            }
         }
 
-        println!("{}", err_str);
-
-        if conf.romfs.is_empty() {
-            let e = format!("Unable to get proper romfs path:\n{}", err_str);
-            return Err(io::Error::new(io::ErrorKind::NotFound, e));
+        Err(Error::RomfsNotFound(format!(
+            "Unable to get proper romfs path. Pass --romfs <path>, set {} or set `romfs` in {}\n{}",
+            ROMFS_ENV,
+            conf.config_path,
+            err_str.trim_end()
+        )))
+    }
+    pub fn get_config_path(&mut self, config_dir: &Path) -> Result<()> {
+        let conf_path = config_dir.join("AgeOfCalamity/config.toml");
+        if !self.dry_run {
+            makedirs(&conf_path)?;
         }
-
-        conf.save().map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Unable to save config to:\n{}\n{:?}", &conf.config_path, e),
-            )
-        })?;
-        Ok(conf)
-    }
-    pub fn get_config_path(&mut self) -> io::Result<()> {
-        let appdata = env::var("LOCALAPPDATA")
-            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "Cannot access appdata"))?;
-        let mut conf_path = PathBuf::from(&appdata);
-        conf_path.push("AgeOfCalamity/config.toml");
-        makedirs(&conf_path)?;
-        self.config_path = conf_path.to_string_lossy().to_string().replace("\\", "/");
+        self.config_path = to_slash_string(&conf_path);
         // println!("config_path {:?}", &self.config_path);
 
         Ok(())
@@ -117,38 +190,82 @@ impl AocConfig {
         dest_path.exists()
     }
 
-    pub fn update_default(&mut self) -> io::Result<()> {
-        let conf_str = fs::read_to_string(&self.config_path)?;
-        let conf: HashMap<String, serde_json::Value> = toml::from_str(&conf_str).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Unable to parse default config\n{:?}", e),
-            )
-        })?;
+    /// Layers only hold the files they change, so they just have to be directories
+    fn check_layers(&self) -> Result<()> {
+        match self.layers.iter().find(|x| !Path::new(x).is_dir()) {
+            Some(layer) => Err(Error::RomfsNotFound(format!("Romfs layer '{}' is not a directory", layer))),
+            None => Ok(()),
+        }
+    }
+
+    /// Base romfs first, then the layers in the order they shadow each other
+    pub fn romfs_roots(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.romfs)
+            .chain(self.layers.iter())
+            .filter(|x| !x.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Every RDB of the layered romfs by file name, taken from the last root that has it
+    pub fn rdb_paths(&self) -> Result<BTreeMap<String, PathBuf>> {
+        let mut res = BTreeMap::new();
+        for (i, root) in self.romfs_roots().into_iter().enumerate() {
+            let asset_dir = root.join("asset");
+            // Layers without RDBs have no asset directory
+            if i > 0 && !asset_dir.is_dir() {
+                continue;
+            }
+            let files = fs::read_dir(&asset_dir)
+                .map_err(|e| Error::RomfsNotFound(format!("{}: {}", asset_dir.display(), e)))?;
+            for file in files {
+                let p = Pathlib::new(file.with_path(&asset_dir)?.path());
+                if p.is_file() && p.full_path.to_lowercase().ends_with(".rdb") {
+                    res.insert(p.name, PathBuf::from(p.full_path));
+                }
+            }
+        }
+        Ok(res)
+    }
+
+    pub fn update_default(&mut self) -> Result<()> {
+        let conf_str = fs::read_to_string(&self.config_path).with_path(&self.config_path)?;
+        let conf: HashMap<String, serde_json::Value> =
+            toml::from_str(&conf_str).with_path(&self.config_path)?;
         let binding = "".into();
         let romfs = conf
             .get("romfs")
             .unwrap_or(&binding)
             .as_str()
             .unwrap_or_default();
+        // Kept even if romfs is invalid, so that picking the base romfs doesn't drop them
+        self.layers = conf
+            .get("layers")
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(|x| to_slash_string(Path::new(x)))
+            .collect();
 
         if Self::check_if_romfs_valid(romfs) {
-            self.romfs = romfs.to_string().replace("\\", "/");
-            return Ok(());
+            self.romfs = to_slash_string(Path::new(romfs));
+            return self.check_layers();
         }
 
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Unable to parse default config",
-        ));
+        Err(Error::RomfsNotFound(format!(
+            "CharacterEditor.rdb not found in '{}' from {}",
+            romfs, self.config_path
+        )))
     }
 
-    pub fn update_from_input(&mut self) -> io::Result<()> {
+    #[cfg(feature = "gui")]
+    pub fn update_from_input(&mut self) -> Result<()> {
         let chosen = rfd::FileDialog::new()
             .set_title("Choose Age of Calamity romfs path")
             .pick_folder()
             .unwrap_or_default();
-        let res = chosen.to_string_lossy().to_string().replace("\\", "/");
+        let res = to_slash_string(&chosen);
         if !Self::check_if_romfs_valid(&res) {
             let e = "Invalid romfs path! CharacterEditor.rdb not found:\n";
             rfd::MessageDialog::new()
@@ -156,21 +273,20 @@ impl AocConfig {
                 .set_title("Invalid romfs path")
                 .set_description(e)
                 .show();
-            return Err(io::Error::new(io::ErrorKind::NotFound, e));
+            return Err(Error::RomfsNotFound(format!("CharacterEditor.rdb not found in '{}'", res)));
         }
         self.romfs = res;
         Ok(())
     }
 
+    /// File of the layered romfs, from the last root that has it
     pub fn get_path(&self, pack_local_path: &str) -> Option<PathBuf> {
         //let pack_local_path = format!("Pack/Actor/{}.pack.zs", name);
-        let romfs = PathBuf::from(&self.romfs);
-        let mut dest_path = romfs.clone();
-        dest_path.push(pack_local_path);
-        if dest_path.exists() {
-            return Some(dest_path);
-        }
-        None
+        self.romfs_roots()
+            .into_iter()
+            .rev()
+            .map(|root| root.join(pack_local_path))
+            .find(|dest_path| dest_path.exists())
     }
 
     pub fn get_rdb_path(&self, name: &str) -> Option<PathBuf> {
@@ -190,73 +306,80 @@ impl AocConfig {
         }
     }
 
-    pub fn get_hashes(&mut self, force_rebuild: bool) -> io::Result<()> {
+    pub fn get_hashes(&mut self, force_rebuild: bool) -> Result<()> {
         let mut json_path = PathBuf::from(&self.config_path);
         if !json_path.pop() {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Config path has no parent",
-            ));
+            return Err(Error::invalid(format!("Config path has no parent: {}", self.config_path)));
         }
         json_path.push("AOC_hashes.json");
+        let roots: Vec<String> = self.romfs_roots().iter().map(|x| to_slash_string(x)).collect();
         if json_path.exists() {
             if force_rebuild {
-                std::fs::remove_file(&json_path)?;
+                if !skip_in_dry_run(self.dry_run, format!("delete {}", json_path.display())) {
+                    std::fs::remove_file(&json_path).with_path(&json_path)?;
+                }
             } else {
-                let json_str = fs::read_to_string(&json_path)?;
-                self.hashes = serde_json::from_str(&json_str)?;
-                self.get_rev_hashes();
-                return Ok(());
+                let json_str = fs::read_to_string(&json_path).with_path(&json_path)?;
+                // Caches from older versions have no roots and are rebuilt as well
+                match serde_json::from_str::<HashesCache>(&json_str) {
+                    Ok(cache) if cache.roots == roots => {
+                        self.hashes = cache.hashes;
+                        self.get_rev_hashes();
+                        return Ok(());
+                    }
+                    _ => println!("The romfs roots changed since the AOC hashes were cached"),
+                }
             }
         }
-        self.hashes_json_path = json_path.to_string_lossy().to_string().replace("\\", "/");
+        self.hashes_json_path = to_slash_string(&json_path);
         println!("Generating cache for AOC hashes, this will be done only once...");
-        // let mut data: HashMap<String, Vec<String>> = HashMap::new();
-        let mut rdb_path = PathBuf::from(&self.romfs);
-        rdb_path.push("asset");
-        for file in std::fs::read_dir(&rdb_path)? {
-            let p = Pathlib::new(file?.path());
-            if p.is_file() && p.full_path.to_lowercase().ends_with(".rdb") {
-                let mut Hashes: Vec<String> = Vec::new();
-                let rdb = Rdb::open(&p.full_path).expect("Failed to open RDB file");
-                for entry in &rdb.entries {
-                    let hash_formatted = format!("{:08x}", entry.file_ktid);
-                    Hashes.push(hash_formatted.clone());
-                    self.hashes_rev.insert(hash_formatted, p.name.clone());
-                }
-                self.hashes.insert(p.name.into(), Hashes);
+        self.hashes.clear();
+        self.hashes_rev.clear();
+        for (name, path) in self.rdb_paths()? {
+            let mut Hashes: Vec<String> = Vec::new();
+            let rdb = Rdb::open(&path)?;
+            for entry in &rdb.entries {
+                let hash_formatted = format!("{:08x}", entry.file_ktid);
+                Hashes.push(hash_formatted.clone());
+                self.hashes_rev.insert(hash_formatted, name.clone());
             }
+            self.hashes.insert(name, Hashes);
+        }
+        if skip_in_dry_run(self.dry_run, format!("write {}", json_path.display())) {
+            return Ok(());
         }
-        serde_json::to_writer(std::fs::File::create(json_path)?, &self.hashes)?;
+        let cache = HashesCache {
+            roots,
+            hashes: self.hashes.clone(),
+        };
+        serde_json::to_writer(std::fs::File::create(&json_path).with_path(&json_path)?, &cache)
+            .with_path(&json_path)?;
         Ok(())
     }
 
-    pub fn save(&self) -> io::Result<()> {
+    pub fn save(&self) -> Result<()> {
         if self.config_path.is_empty() {
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Empty config path"));
+            return Err(Error::invalid("Empty config path"));
         }
-        makedirs(&PathBuf::from(&self.config_path))?;
+        if skip_in_dry_run(self.dry_run, format!("write {}", self.config_path)) {
+            return Ok(());
+        }
+        makedirs(PathBuf::from(&self.config_path))?;
         // let json_str: String = serde_json::to_string_pretty(self)?;
         let json_data = self.to_json()?;
-        let toml_str = toml::to_string_pretty(&json_data)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#?}", e)))?;
+        let toml_str = toml::to_string_pretty(&json_data)?;
         // write_string_to_file(&self.config_path, &json_str)?;
         let mut res = String::new();
         res.push_str("# Age of Calamity rdb tool merging configuration file\n");
         res.push_str("# \n");
         res.push_str(&toml_str);
-        std::fs::write(&self.config_path, &res)?;
+        std::fs::write(&self.config_path, &res).with_path(&self.config_path)?;
         Ok(())
     }
 
-    fn try_save_config(&mut self) -> io::Result<bool> {
+    fn try_save_config(&mut self) -> Result<bool> {
         if !self.romfs.is_empty() {
-            self.save().map_err(|e| {
-                io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("Unable to save config to:\n{}\n{:?}", &self.config_path, e),
-                )
-            })?;
+            self.save()?;
             self.get_hashes(false)?;
             Ok(true)
         } else {
@@ -265,17 +388,17 @@ impl AocConfig {
     }
 }
 
-pub fn makedirs<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
+pub fn makedirs<P: AsRef<Path>>(path: P) -> Result<()> {
     let binding = path.as_ref();
     let par = Path::new(&binding).parent();
     if let Some(par) = par {
-        fs::create_dir_all(par)?;
+        fs::create_dir_all(par).with_path(par)?;
     }
     Ok(())
 }
 
-fn log_error(err_str: &mut String, err: io::Error) {
-    let e = format!("{:#?}\n", err);
+fn log_error(err_str: &mut String, err: Error) {
+    let e = format!("{}\n", err);
     println!("{}", &e);
     err_str.push_str(&e);
 }
@@ -322,7 +445,7 @@ impl Pathlib {
         if !extension.contains('.') {
             return "".to_string();
         }
-        extension.split('.').last().unwrap_or_default().to_string()
+        extension.split('.').next_back().unwrap_or_default().to_string()
     }
 
     pub fn get_parent<P: AsRef<Path>>(path: P) -> String {
@@ -370,12 +493,118 @@ impl Pathlib {
     }
 }
 
+/// Per-user configuration directory: `%LOCALAPPDATA%` on Windows, `~/Library/Application Support`
+/// on macOS and `$XDG_CONFIG_HOME` (`~/.config` by default) elsewhere
+pub fn config_dir() -> Result<PathBuf> {
+    let var = |name: &str| env::var_os(name).filter(|x| !x.is_empty()).map(PathBuf::from);
+    let not_found = |what: &str| Error::invalid(format!("Cannot access {}", what));
+
+    if cfg!(windows) {
+        var("LOCALAPPDATA").ok_or_else(|| not_found("appdata"))
+    } else if cfg!(target_os = "macos") {
+        var("HOME")
+            .map(|home| home.join("Library/Application Support"))
+            .ok_or_else(|| not_found("the home directory"))
+    } else {
+        var("XDG_CONFIG_HOME")
+            .filter(|x| x.is_absolute())
+            .or_else(|| var("HOME").map(|home| home.join(".config")))
+            .ok_or_else(|| not_found("the home directory"))
+    }
+}
 
-pub fn normalize_path(path: PathBuf) -> PathBuf {
-    let prefix = r"\\?\";
-    if path.to_str().map_or(false, |s| s.starts_with(prefix)) {
-        PathBuf::from(path.to_str().unwrap().trim_start_matches(prefix))
+/// Path as a string with `/` separators. Backslashes are only separators on Windows,
+/// elsewhere they are valid file name characters and are kept.
+pub fn to_slash_string(path: &Path) -> String {
+    let path = path.to_string_lossy().to_string();
+    if cfg!(windows) {
+        path.replace('\\', "/")
     } else {
         path
     }
-}
\ No newline at end of file
+}
+
+/// Strip the `\\?\` verbatim prefix `canonicalize` adds on Windows
+pub fn normalize_path(path: PathBuf) -> PathBuf {
+    let prefix = r"\\?\";
+    match path.to_str().and_then(|s| s.strip_prefix(prefix)) {
+        Some(stripped) if cfg!(windows) => PathBuf::from(stripped),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_shadow_base() {
+        let dir = env::temp_dir().join(format!("rdb_tool_layers_{}", std::process::id()));
+        let roots = ["base", "update", "dlc"].map(|x| dir.join(x));
+        for (root, files) in roots.iter().zip([&["CharacterEditor.rdb", "A.rdb"][..], &["A.rdb"], &[]]) {
+            fs::create_dir_all(root.join("asset")).unwrap();
+            for file in files {
+                fs::write(root.join("asset").join(file), b"").unwrap();
+            }
+        }
+        let conf = AocConfig {
+            romfs: to_slash_string(&roots[0]),
+            layers: roots[1..].iter().map(|x| to_slash_string(x)).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(conf.get_rdb_path("A"), Some(roots[1].join("asset/A.rdb")));
+        assert_eq!(
+            conf.get_rdb_path("CharacterEditor.rdb"),
+            Some(roots[0].join("asset/CharacterEditor.rdb"))
+        );
+        let rdbs = conf.rdb_paths().unwrap();
+        assert_eq!(rdbs.len(), 2);
+        assert_eq!(rdbs["A.rdb"], roots[1].join("asset/A.rdb"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn romfs_sources() {
+        let dir = env::temp_dir().join(format!("rdb_tool_config_{}", std::process::id()));
+        let romfs = ["base", "update"].map(|x| dir.join(x));
+        for root in &romfs {
+            fs::create_dir_all(root.join("asset")).unwrap();
+            fs::write(root.join("asset/CharacterEditor.rdb"), crate::rdb::tests::sample_rdb()).unwrap();
+        }
+
+        // --romfs takes precedence over AOC_ROMFS, which holds a list of roots
+        let mut options = ConfigOptions {
+            romfs: Vec::new(),
+            gui: false,
+            dry_run: true,
+        };
+        let romfs_env = Some(env::join_paths(&romfs).unwrap());
+        assert_eq!(options.romfs_override_from(None), None);
+        assert_eq!(options.romfs_override_from(romfs_env.clone()), Some(romfs.to_vec()));
+        options.romfs = vec![dir.join("missing")];
+        assert_eq!(options.romfs_override_from(romfs_env), Some(vec![dir.join("missing")]));
+
+        // An invalid override fails without falling back to anything else
+        assert!(matches!(AocConfig::new_in(&options, &dir, None), Err(Error::RomfsNotFound(_))));
+
+        // Nothing configured and no dialog allowed
+        options.romfs.clear();
+        match AocConfig::new_in(&options, &dir, None) {
+            Err(Error::RomfsNotFound(message)) => assert!(message.contains("--romfs")),
+            x => panic!("expected RomfsNotFound, got {:?}", x.map(|x| x.romfs)),
+        }
+
+        // The config file, unless AOC_ROMFS is set
+        let config_path = dir.join("AgeOfCalamity/config.toml");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        fs::write(&config_path, format!("romfs = {:?}\n", to_slash_string(&romfs[0]))).unwrap();
+        let conf = AocConfig::new_in(&options, &dir, None).unwrap();
+        assert_eq!(conf.romfs_roots(), vec![romfs[0].clone()]);
+        let conf = AocConfig::new_in(&options, &dir, Some(romfs[1].clone().into())).unwrap();
+        assert_eq!(conf.romfs_roots(), vec![romfs[1].clone()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,51 +1,49 @@
-use io::Error as ioErr;
-use io::ErrorKind as ErrKind;
 use std::{
-    collections::HashMap,
-    fmt, fs, io,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
     path::{Path, PathBuf},
-    process,
     sync::Arc,
 };
 
 use crate::{
-    rdb::{self, Rdb},
+    compression::Compression,
+    error::{Context, Error, Result},
+    ktid::KTID,
+    load_order::{LoadOrder, LOAD_ORDER_FILE},
+    merge::{merge_rdbs, Conflict, ConflictKind},
+    merge_state::{hash_bytes, ContentHash, InputFile, MergeState, OutputFile, RdbState, MERGE_STATE_FILE},
+    rollback::Backup,
+    mod_info::{self, ModInfo},
+    rdb::Rdb,
     utils::*,
-    AocConfig::{AocConfig, Pathlib},
+    AocConfig::{AocConfig, ConfigOptions, Pathlib},
 };
 
-#[derive(Debug, Clone)]
+/// Folder the mods are merged into, next to them
+pub const MERGED_MOD_NAME: &str = "000_AOC_MERGED_MODS";
+
+#[derive(Debug, Clone, Default)]
 pub struct ModDir {
     pub path: PathBuf,
     pub rdb_path: PathBuf,
     pub data_path: PathBuf,
+    /// Where older versions of the merger moved the files of `data_path`, still read if present
     pub new_data_path: PathBuf,
     pub patch_path: PathBuf,
     pub add_paths: Vec<PathBuf>,
-}
-
-impl Default for ModDir {
-    fn default() -> Self {
-        Self {
-            path: PathBuf::default(),
-            rdb_path: PathBuf::default(),
-            data_path: PathBuf::default(),
-            new_data_path: PathBuf::default(),
-            patch_path: PathBuf::default(),
-            add_paths: Vec::new(),
-        }
-    }
+    /// Content of the optional `mod.toml`
+    pub info: Option<ModInfo>,
 }
 
 impl ModDir {
     pub fn new<P: AsRef<Path>>(path: P, add_paths: Vec<PathBuf>, create_dirs: bool) -> Self {
         let p = PathBuf::from(path.as_ref());
-        let mut rdb_path = p.clone().join("romfs/asset");
+        let rdb_path = p.clone().join("romfs/asset");
         // rdb_path.push("romfs/asset");
-        let mut data_path = p.clone().join("romfs/asset/data");
-        let mut new_data_path = p.clone().join("data");
+        let data_path = p.clone().join("romfs/asset/data");
+        let new_data_path = p.clone().join("data");
         // data_path.push("romfs/asset/data");
-        let mut patch_path = p.clone().join("romfs/asset/patch");
+        let patch_path = p.clone().join("romfs/asset/patch");
         let new_add_paths = Vec::new();
         // patch_path.push("romfs/asset/patch");
         if create_dirs {
@@ -61,27 +59,33 @@ impl ModDir {
         }
         Self {
             path: p,
-            rdb_path: rdb_path,
-            data_path: data_path,
-            new_data_path: new_data_path,
-            patch_path: patch_path,
+            rdb_path,
+            data_path,
+            new_data_path,
+            patch_path,
             add_paths: new_add_paths,
+            info: None,
         }
     }
-    pub fn remove_self_if_exists(&self) -> io::Result<()> {
+    /// Name of the mod folder
+    pub fn name(&self) -> String {
+        Pathlib::new(&self.path).name
+    }
+
+    pub fn remove_self_if_exists(&self) -> Result<()> {
         if self.path.exists() {
-            fs::remove_dir_all(&self.path)?;
+            fs::remove_dir_all(&self.path).with_path(&self.path)?;
         }
         Ok(())
     }
 
-    pub fn create_dirs_all(&self) -> io::Result<()> {
-        fs::create_dir_all(&self.path)?;
-        fs::create_dir_all(&self.data_path)?;
-        fs::create_dir_all(&self.new_data_path)?;
-        fs::create_dir_all(&self.patch_path)?;
-        for add_path in self.add_paths.iter() {
-            fs::create_dir_all(add_path)?;
+    pub fn create_dirs_all(&self) -> Result<()> {
+        for dir in [&self.path, &self.data_path, &self.new_data_path, &self.patch_path]
+            .iter()
+            .copied()
+            .chain(self.add_paths.iter())
+        {
+            fs::create_dir_all(dir).with_path(dir)?;
         }
         Ok(())
     }
@@ -94,34 +98,65 @@ pub struct ModMerger {
     pub root_dir: ModDir,
     pub mods_dirs: Vec<ModDir>,
     pub add_paths: Vec<String>,
-    // pub rdbs: HashMap<String, Rdb>,
+    /// RDBs merged from the ones shipped by the mods
+    pub rdbs: HashMap<String, Rdb>,
+    pub conflicts: Vec<Conflict>,
     pub aoc_hashes: HashMap<String, Vec<AocHash>>,
+    pub compression: Compression,
+    /// Stop before writing anything if several mods touch the same KTID
+    pub fail_on_conflict: bool,
+    pub load_order: LoadOrder,
+    /// Mod that must win a KTID, from the load order
+    pub overrides: HashMap<u32, String>,
+    /// Only print what would be moved, written and deleted
+    pub dry_run: bool,
+    /// Mods shipping an edited version of each RDB
+    pub rdb_sources: HashMap<String, Vec<String>>,
+    /// Set while writing the merged mod
+    pub backup: Option<Backup>,
 }
 
 impl ModMerger {
-    pub fn new_default() -> io::Result<Self> {
-        Ok(Self {
-            config: Arc::new(AocConfig::new()?),
-            root_mod_name: "000_AOC_MERGED_MODS".to_string(),
+    pub fn new_default(options: &ConfigOptions) -> Result<Self> {
+        Ok(Self::with_config(AocConfig::new(options)?))
+    }
+
+    /// Merger using an already resolved config
+    pub fn with_config(config: AocConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            root_mod_name: MERGED_MOD_NAME.to_string(),
             cwd_dir: Default::default(),
             root_dir: Default::default(),
             mods_dirs: Vec::new(),
             add_paths: vec!["exefs".to_string(), "romfs/movie_logo".to_string()],
-            // rdbs: HashMap::new(),
+            rdbs: HashMap::new(),
+            conflicts: Vec::new(),
             aoc_hashes: Default::default(),
-        })
+            compression: Compression::None,
+            fail_on_conflict: false,
+            load_order: Default::default(),
+            overrides: HashMap::new(),
+            dry_run: false,
+            rdb_sources: HashMap::new(),
+            backup: None,
+        }
+    }
+    pub fn new<P: AsRef<Path>>(cwd_dir: Option<P>, options: &ConfigOptions) -> Result<Self> {
+        Ok(Self::new_with_config(cwd_dir, AocConfig::new(options)?))
     }
-    pub fn new<P: AsRef<Path>>(cwd_dir: Option<P>) -> io::Result<Self> {
-        let mut rdir = String::new();
-        if let Some(p) = cwd_dir {
-            rdir = p.as_ref().to_string_lossy().to_string();
+
+    /// Merger of the mods in `cwd_dir` (the current directory by default) using an already resolved config
+    pub fn new_with_config<P: AsRef<Path>>(cwd_dir: Option<P>, config: AocConfig) -> Self {
+        let rdir = if let Some(p) = cwd_dir {
+            p.as_ref().to_string_lossy().to_string()
         } else {
-            rdir = std::env::current_dir()
+            std::env::current_dir()
                 .unwrap_or_default()
                 .to_string_lossy()
-                .to_string();
-        }
-        let mut res = Self::new_default()?;
+                .to_string()
+        };
+        let mut res = Self::with_config(config);
         let mut add_paths = Vec::new();
         for add_path in res.add_paths.iter() {
             let mut p = PathBuf::from(&rdir);
@@ -130,18 +165,14 @@ impl ModMerger {
         }
         res.cwd_dir = rdir.clone();
         res.root_dir = ModDir::new(Path::new(&rdir).join(&res.root_mod_name), add_paths, false);
-        res.root_dir.remove_self_if_exists()?;
-        res.root_dir.create_dirs_all()?;
         // res.get_mods_dirs()?;
-        Ok(res)
+        res
     }
 
-    pub fn process_mods(&mut self) -> io::Result<()> {
-        let rdb_dest_dir = self.root_dir.rdb_path.clone();
-        // println!("{}:{}: rdb_dest_dir {:?}", file!(), line!(), &rdb_dest_dir);
+    pub fn process_mods(&mut self) -> Result<()> {
 
-        for entry in fs::read_dir(&self.cwd_dir)? {
-            let path = Pathlib::new(entry?.path());
+        for entry in fs::read_dir(&self.cwd_dir).with_path(&self.cwd_dir)? {
+            let path = Pathlib::new(entry.with_path(&self.cwd_dir)?.path());
             if path.is_dir()
                 && path.name != self.root_mod_name
                 && self.is_valid_mod_dir(&path.full_path)
@@ -154,113 +185,521 @@ impl ModMerger {
                 .to_lowercase()
                 .cmp(&b.path.to_string_lossy().to_lowercase())
         });
+        let new_mods = self.apply_load_order()?;
         if self.mods_dirs.is_empty() {
             eprintln!("No mods to merge found in the directory: {}", &self.cwd_dir);
             return Ok(());
         }
         println!("Age Of Calamity Mods Merger 1.0\nMerging {} mods\n\n", self.mods_dirs.len());
+        self.check_mod_infos()?;
+        self.merge_mod_rdbs()?;
+        self.conflicts.extend(self.find_file_conflicts()?);
+        self.print_conflict_report();
+        if self.fail_on_conflict && !self.conflicts.is_empty() {
+            return Err(Error::invalid(format!(
+                "{} conflicts found, nothing was written",
+                self.conflicts.len()
+            )));
+        }
+        if new_mods {
+            self.save_load_order()?;
+        }
+
+        if !self.dry_run {
+            self.backup = Some(Backup::start(&self.root_dir.path)?);
+        }
+        let result = self.write_merged_mod();
+        // Even a failed merge can be rolled back
+        if let Some(backup) = &self.backup {
+            backup.save_journal()?;
+        }
+        result
+    }
+
+    /// Write the merged RDBs and files, reusing what the previous merge left when its inputs didn't change
+    fn write_merged_mod(&mut self) -> Result<()> {
+        let rdb_dest_dir = self.root_dir.rdb_path.clone();
+
+        // Outputs are only reused if the previous merge was made by this version with the same settings
+        let state_path = self.root_dir.path.join(MERGE_STATE_FILE);
+        let settings = self.settings_hash();
+        let previous = MergeState::open(&state_path).filter(|x| x.settings == settings);
+        let mut state = MergeState::new(settings, self.mods_dirs.iter().map(ModDir::name).collect());
+        if let Some(previous) = &previous {
+            if previous.mods != state.mods {
+                println!("Load order changed since the last merge");
+            }
+        } else if self.root_dir.path.exists() {
+            self.clear_merged_mod()?;
+        }
+        if !self.root_dir.rdb_path.exists()
+            && !skip_in_dry_run(self.dry_run, format!("create {}", self.root_dir.path.display()))
+        {
+            self.root_dir.create_dirs_all()?;
+        }
+
         for mod_dir in self.mods_dirs.clone().iter().rev().cloned() {
             println!("Processing mod directory: {}", &mod_dir.path.display());
-            fs::create_dir_all(&mod_dir.new_data_path)?;
-            self.copy_from_data_path_to_new_data_path(&mod_dir)?;
-            self.copy_add_paths(&mod_dir)?;
-            self.update_aoc_hashes_from_modpath(mod_dir)?;
+            let mod_name = mod_dir.name();
+            self.copy_add_paths(&mod_dir, previous.as_ref(), &mut state)
+                .in_mod(&mod_name)?;
+            self.update_aoc_hashes_from_modpath(mod_dir).in_mod(&mod_name)?;
+        }
+        if let Some(previous) = &previous {
+            for (_, add_file) in previous.add_files.iter().filter(|(x, _)| !state.add_files.contains_key(*x)) {
+                self.remove_output(&self.root_dir.path.join(&add_file.path))?;
+            }
         }
         println!("\n\n");
         // println!("{}:{}: aoc_hashes {:?}", file!(), line!(), &self.aoc_hashes);
 
-        for (rdb_name, hashes) in self.aoc_hashes.iter_mut() {
-            if !hashes.is_empty() {
-                if let Some(rdb_path) = self.config.get_rdb_path(&rdb_name) {
-                    let mut rdb = Rdb::open_io(rdb_path)?;
-                    println!("Starting to patch {}", rdb_name);
-                    let mut processed_hashes:Vec<&str> = Vec::new();
-                    for aoc_hash in hashes.iter_mut() {
-                        let filename = &aoc_hash.as_hex_str();
-                        if processed_hashes.contains(&&aoc_hash.hash.as_str()) {
-                            continue;
+        let mut rdb_names: Vec<String> = self.aoc_hashes.keys().chain(self.rdbs.keys()).cloned().collect();
+        rdb_names.sort();
+        rdb_names.dedup();
+
+        for rdb_name in rdb_names.iter() {
+            let rdb_dest_path = rdb_dest_dir.join(rdb_name);
+            let inputs = self.rdb_inputs_hash(rdb_name, previous.as_ref(), &mut state)?;
+            let previous_rdb = previous.as_ref().and_then(|x| x.rdbs.get(rdb_name));
+            let up_to_date = |x: &&RdbState| {
+                x.inputs == inputs
+                    && rdb_dest_path.exists()
+                    && x.files.values().all(|file| self.root_dir.path.join(&file.path).exists())
+            };
+            if let Some(previous_rdb) = previous_rdb.filter(up_to_date) {
+                println!("{} is up to date", rdb_name);
+                state.rdbs.insert(rdb_name.to_string(), previous_rdb.clone());
+                continue;
+            }
+
+            let mut rdb = match self.rdbs.remove(rdb_name) {
+                Some(rdb) => rdb,
+                None => match self.config.get_rdb_path(rdb_name) {
+                    Some(rdb_path) => Rdb::open(rdb_path)?,
+                    None => {
+                        eprintln!("ERROR: RDB not found for {}", rdb_name);
+                        continue;
+                    }
+                },
+            };
+            let mut rdb_state = RdbState {
+                path: format!("romfs/asset/{}", rdb_name),
+                inputs,
+                mods: Vec::new(),
+                files: BTreeMap::new(),
+            };
+            if let Some(hashes) = self.aoc_hashes.get(rdb_name) {
+                println!("Starting to patch {}", rdb_name);
+                let mut processed_hashes:Vec<&str> = Vec::new();
+                for aoc_hash in hashes.iter() {
+                    let filename = &aoc_hash.as_hex_str();
+                    if processed_hashes.contains(&aoc_hash.hash.as_str()) {
+                        continue;
+                    }
+                    let source = self.source_mod(&aoc_hash.path.full_path);
+                    let ktid = aoc_hash.as_u32().in_mod(&source)?;
+                    match rdb.get_entry_by_ktid_mut(KTID(ktid)) {
+                        Some(entry_found) if entry_found.is_encrypted() => {
+                            eprintln!(
+                                "WARNING: {} ({}) targets an encrypted entry of {}, which cannot be patched. Skipping.",
+                                &aoc_hash.path.name, filename, rdb_name
+                            );
                         }
-                        match rdb.get_entry_by_ktid_mut(crate::ktid(filename)) {
-                            Some(entry_found) => {
-                                print!("Patching {} ... ", &aoc_hash.path.name);
-                                entry_found.make_external();
-                                entry_found.make_uncompressed();
-                                let destpath = self.root_dir.data_path.join(format!("0x{}.file", &aoc_hash.hash));
-                                if let Ok(rawdata) = entry_found.set_external_file(&aoc_hash) {
-                                    println!("Entry converted nicely");
-                                    fs::write(&destpath, &rawdata)?;
-                                } else {
-                                    //assuming the file needs to be copied
-                                    if !destpath.exists() {
-                                        println!("Entry already converted, copying");
-                                        fs::copy(&aoc_hash.path.full_path, &destpath)?;
-                                    }
-                                }
+                        Some(entry_found) => {
+                            print!("Patching {} ... ", &aoc_hash.path.name);
+                            let destname = format!("0x{}.file", &aoc_hash.hash);
+                            let rawdata = entry_found
+                                .set_external_file(aoc_hash, self.compression)
+                                .in_mod(&source)?;
+                            println!("done");
+                            let output = OutputFile {
+                                path: format!("romfs/asset/data/{}", destname),
+                                hash: hash_bytes(&rawdata),
+                                source,
+                            };
+                            let destpath = self.root_dir.path.join(&output.path);
+                            let unchanged = previous_rdb
+                                .and_then(|x| x.files.get(&destname))
+                                .is_some_and(|x| x.hash == output.hash && destpath.exists());
+                            if !unchanged {
+                                self.write_output(&destpath, &rawdata)?;
                             }
-                            None => println!("File {} not found in the RDB. Skipping.", filename),
+                            rdb_state.files.insert(destname, output);
                         }
-                        processed_hashes.push(&aoc_hash.hash);
+                        None => println!("File {} not found in the RDB. Skipping.", filename),
                     }
-                    let rdb_dest_path = rdb_dest_dir.join(rdb_name);
-                    rdb.save(&rdb_dest_path)?;
+                    processed_hashes.push(&aoc_hash.hash);
+                }
+            }
+            if let Some(previous_rdb) = previous_rdb {
+                for (_, stale) in previous_rdb.files.iter().filter(|(x, _)| !rdb_state.files.contains_key(*x)) {
+                    self.remove_output(&self.root_dir.path.join(&stale.path))?;
                 }
-            } else {
-                eprintln!("ERROR: RDB not found for {}", rdb_name);
             }
+            self.write_output(&rdb_dest_path, &rdb.to_bytes()?)?;
+            rdb_state.mods = self
+                .mods_dirs
+                .iter()
+                .map(ModDir::name)
+                .filter(|x| {
+                    self.rdb_sources.get(rdb_name).is_some_and(|mods| mods.contains(x))
+                        || rdb_state.files.values().any(|file| file.source == *x)
+                })
+                .collect();
+            state.rdbs.insert(rdb_name.to_string(), rdb_state);
             println!("\n\n");
         }
 
+        // Outputs of RDBs no mod touches anymore
+        if let Some(previous) = &previous {
+            for (_, previous_rdb) in previous.rdbs.iter().filter(|(x, _)| !state.rdbs.contains_key(*x)) {
+                self.remove_output(&self.root_dir.path.join(&previous_rdb.path))?;
+                for stale in previous_rdb.files.values() {
+                    self.remove_output(&self.root_dir.path.join(&stale.path))?;
+                }
+            }
+        }
+        if previous.as_ref() != Some(&state) {
+            self.write_output(&state_path, serde_json::to_string_pretty(&state)?.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Hash of the options that change every output
+    fn settings_hash(&self) -> String {
+        let mut hash = ContentHash::default();
+        hash.update(self.compression.to_string().as_bytes());
+        let mut overrides: Vec<_> = self.overrides.iter().collect();
+        overrides.sort();
+        for (ktid, mod_name) in overrides {
+            hash.update(&ktid.to_le_bytes());
+            hash.update(mod_name.as_bytes());
+        }
+        hash.hex()
+    }
+
+    /// Hash of everything a merged RDB is made from: the vanilla RDB, the RDBs of the same name
+    /// shipped by mods and the data files targeting it, in priority order
+    fn rdb_inputs_hash(
+        &self,
+        rdb_name: &str,
+        previous: Option<&MergeState>,
+        state: &mut MergeState,
+    ) -> Result<String> {
+        let mut hash = ContentHash::default();
+        if let Some(rdb_path) = self.config.get_rdb_path(rdb_name) {
+            hash.update(input_file_hash(&rdb_path, previous, state)?.as_bytes());
+        }
+        for mod_dir in self.mods_dirs.iter() {
+            let mod_rdb_path = mod_dir.rdb_path.join(rdb_name);
+            if mod_rdb_path.is_file() {
+                hash.update(mod_dir.name().as_bytes());
+                hash.update(input_file_hash(&mod_rdb_path, previous, state)?.as_bytes());
+            }
+        }
+        for aoc_hash in self.aoc_hashes.get(rdb_name).into_iter().flatten() {
+            hash.update(aoc_hash.path.full_path.as_bytes());
+            hash.update(input_file_hash(&aoc_hash.path.full_path, previous, state)?.as_bytes());
+        }
+        Ok(hash.hex())
+    }
+
+    /// Write a file of the merged mod, backing up its previous version for `rollback`
+    fn write_output(&self, path: &Path, data: &[u8]) -> Result<()> {
+        if skip_in_dry_run(self.dry_run, format!("write {}", path.display())) {
+            return Ok(());
+        }
+        if let Some(backup) = &self.backup {
+            backup.save(path)?;
+        }
+        fs::write(path, data).with_path(path)
+    }
+
+    /// Delete an output of a previous merge that isn't produced anymore
+    fn remove_output(&self, path: &Path) -> Result<()> {
+        if path.exists() && !skip_in_dry_run(self.dry_run, format!("delete {}", path.display())) {
+            if let Some(backup) = &self.backup {
+                backup.save(path)?;
+            }
+            fs::remove_file(path).with_path(path)?;
+        }
+        Ok(())
+    }
+
+    /// Delete everything in the merged mod, file by file so that it can be rolled back
+    fn clear_merged_mod(&self) -> Result<()> {
+        for entry in walkdir::WalkDir::new(&self.root_dir.path).into_iter().flatten() {
+            if entry.file_type().is_file() {
+                self.remove_output(entry.path())?;
+            }
+        }
+        if !self.dry_run {
+            self.root_dir.remove_self_if_exists()?;
+        }
+        Ok(())
+    }
+
+    /// Name of the mod a file comes from
+    fn source_mod(&self, path: &str) -> String {
+        self.mods_dirs
+            .iter()
+            .find(|x| Path::new(path).starts_with(&x.path))
+            .map(ModDir::name)
+            .unwrap_or_default()
+    }
+
+    /// Sort the mods following `load_order.toml` and drop the disabled ones.
+    /// New mod folders are appended to the load order, returns whether there were any.
+    pub fn apply_load_order(&mut self) -> Result<bool> {
+        let load_order_path = Path::new(&self.cwd_dir).join(LOAD_ORDER_FILE);
+        self.load_order = LoadOrder::open(&load_order_path)?;
+
+        let names: Vec<String> = self.mods_dirs.iter().map(ModDir::name).collect();
+        let new_mods = self.load_order.add_new_mods(&names);
+
+        let load_order = &self.load_order;
+        self.mods_dirs.retain(|mod_dir| {
+            let enabled = load_order.is_enabled(&mod_dir.name());
+            if !enabled {
+                println!("Skipping disabled mod: {}", mod_dir.name());
+            }
+            enabled
+        });
+        self.mods_dirs.sort_by_key(|mod_dir| load_order.priority(&mod_dir.name()));
+        self.overrides = load_order.overrides()?;
+
+        Ok(new_mods)
+    }
+
+    /// Write `load_order.toml` with the new mod folders, created if needed.
+    /// Only done once the checks passed, so a merge that stops early leaves it untouched.
+    pub fn save_load_order(&self) -> Result<()> {
+        let load_order_path = Path::new(&self.cwd_dir).join(LOAD_ORDER_FILE);
+        if !skip_in_dry_run(self.dry_run, format!("write {}", load_order_path.display())) {
+            self.load_order.save(&load_order_path)?;
+            println!("Updated load order: {}", load_order_path.display());
+        }
+        Ok(())
+    }
+
+    /// Read every `mod.toml`, print the summary table and stop if requirements aren't met
+    pub fn check_mod_infos(&mut self) -> Result<()> {
+        for mod_dir in self.mods_dirs.iter_mut() {
+            mod_dir.info = ModInfo::open(&mod_dir.path).in_mod(&mod_dir.name())?;
+        }
+
+        let mods: Vec<(String, Option<ModInfo>)> = self
+            .mods_dirs
+            .iter()
+            .map(|x| (x.name(), x.info.clone()))
+            .collect();
+        println!("{}", mod_info::summary_table(&mods));
+
+        let problems = mod_info::check_dependencies(&mods);
+        if problems.is_empty() {
+            return Ok(());
+        }
+        for problem in problems.iter() {
+            eprintln!("ERROR: {}", problem);
+        }
+        Err(Error::invalid(format!(
+            "{} dependency problems between mods, nothing was written",
+            problems.len()
+        )))
+    }
+
+    /// Three-way merge of every RDB shipped in the mods' `romfs/asset` against the vanilla one
+    pub fn merge_mod_rdbs(&mut self) -> Result<()> {
+        let mut mod_rdbs: HashMap<String, Vec<(String, Rdb)>> = HashMap::new();
+        for mod_dir in self.mods_dirs.iter() {
+            let Ok(entries) = fs::read_dir(&mod_dir.rdb_path) else {
+                continue;
+            };
+            let mod_name = mod_dir.name();
+            for entry in entries.flatten() {
+                let path = Pathlib::new(entry.path());
+                if !path.is_file() || !path.name.to_lowercase().ends_with(".rdb") {
+                    continue;
+                }
+                match Rdb::open(&path.full_path).in_mod(&mod_name) {
+                    Ok(rdb) => mod_rdbs.entry(path.name).or_default().push((mod_name.clone(), rdb)),
+                    Err(e) => eprintln!("ERROR: {}", e),
+                }
+            }
+        }
+
+        let mut removes: HashMap<String, HashSet<u32>> = HashMap::new();
+        for mod_dir in self.mods_dirs.iter() {
+            if let Some(info) = &mod_dir.info {
+                removes.insert(mod_dir.name(), info.removed_ktids().in_mod(&mod_dir.name())?);
+            }
+        }
+
+        for (rdb_name, mods) in mod_rdbs {
+            let Some(rdb_path) = self.config.get_rdb_path(&rdb_name) else {
+                eprintln!("ERROR: Vanilla RDB not found for {}, ignoring the modded ones", rdb_name);
+                continue;
+            };
+            let merge = merge_rdbs(&rdb_name, Rdb::open(rdb_path)?, &mods, &self.overrides, &removes);
+            println!(
+                "Merged {} from {} mods: {} entries changed, {} conflicts",
+                rdb_name,
+                mods.len(),
+                merge.changed,
+                merge.conflicts.len()
+            );
+            for (mod_name, _) in mods.iter() {
+                let kept = merge.kept.iter().filter(|(x, _)| x == mod_name).count();
+                if kept > 0 {
+                    eprintln!(
+                        "WARNING: {} vanilla entries are missing from the {} of {}, they were kept. \
+                         List them in `removes` of its mod.toml to delete them.",
+                        kept, rdb_name, mod_name
+                    );
+                }
+            }
+            self.conflicts.extend(merge.conflicts);
+            self.rdb_sources
+                .insert(rdb_name.clone(), mods.iter().map(|(mod_name, _)| mod_name.clone()).collect());
+            self.rdbs.insert(rdb_name, merge.rdb);
+        }
+
         Ok(())
     }
 
-    pub fn copy_add_paths(&self, mod_dir: &ModDir) ->io::Result<()> {
+    /// Every KTID with a replacement file in more than one mod, read without moving anything
+    pub fn find_file_conflicts(&self) -> Result<Vec<Conflict>> {
+        let mut providers: BTreeMap<u32, (String, Vec<String>)> = BTreeMap::new();
+        for mod_dir in self.mods_dirs.iter() {
+            let mod_name = mod_dir.name();
+            for data_dir in [&mod_dir.data_path, &mod_dir.new_data_path] {
+                let Ok(entries) = fs::read_dir(data_dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let aoc_hash = self.aoc_hash(entry.path());
+                    let (Some(rdb_name), Ok(ktid)) = (&aoc_hash.rdb_name, aoc_hash.as_u32()) else {
+                        continue;
+                    };
+                    let (_, mods) = providers
+                        .entry(ktid)
+                        .or_insert_with(|| (rdb_name.to_string(), Vec::new()));
+                    if !mods.contains(&mod_name) {
+                        mods.push(mod_name.clone());
+                    }
+                }
+            }
+        }
+
+        let mut vanilla_rdbs: HashMap<String, Rdb> = HashMap::new();
+        let mut conflicts = Vec::new();
+        for (ktid, (rdb_name, mods)) in providers {
+            if mods.len() < 2 {
+                continue;
+            }
+            if !self.rdbs.contains_key(&rdb_name) && !vanilla_rdbs.contains_key(&rdb_name) {
+                if let Some(rdb_path) = self.config.get_rdb_path(&rdb_name) {
+                    vanilla_rdbs.insert(rdb_name.clone(), Rdb::open(rdb_path)?);
+                }
+            }
+            let name = self
+                .rdbs
+                .get(&rdb_name)
+                .or_else(|| vanilla_rdbs.get(&rdb_name))
+                .and_then(|rdb| rdb.entries.iter().find(|x| x.file_ktid == ktid))
+                .map(|x| x.get_resource_file_name().display().to_string())
+                .unwrap_or_else(|| format!("0x{:08x}.file", ktid));
+            conflicts.push(Conflict {
+                kind: ConflictKind::File,
+                rdb_name,
+                ktid,
+                name,
+                winner: self
+                    .overrides
+                    .get(&ktid)
+                    .filter(|x| mods.contains(x))
+                    .or(mods.last())
+                    .cloned()
+                    .unwrap_or_default(),
+                mods,
+            });
+        }
+
+        Ok(conflicts)
+    }
+
+    pub fn print_conflict_report(&self) {
+        if self.conflicts.is_empty() {
+            println!("No conflicts between mods\n");
+            return;
+        }
+        println!("Found {} conflicts between mods:", self.conflicts.len());
+        for conflict in self.conflicts.iter() {
+            println!("{}", conflict);
+        }
+        println!();
+    }
+
+    /// `AocHash` of a file, also looking for its KTID in the merged RDBs for entries added by mods
+    pub fn aoc_hash<P: AsRef<Path>>(&self, path: P) -> AocHash {
+        let mut aoc_hash = AocHash::new(path, self.config.clone());
+        if aoc_hash.rdb_name.is_none() {
+            if let Ok(ktid) = aoc_hash.as_u32() {
+                aoc_hash.rdb_name = self
+                    .rdbs
+                    .iter()
+                    .find(|(_, rdb)| rdb.entries.iter().any(|x| x.file_ktid == ktid))
+                    .map(|(rdb_name, _)| rdb_name.to_string());
+            }
+        }
+        aoc_hash
+    }
+
+    /// Copy the add paths (exefs, ...) of a mod, skipping the files a higher priority mod already provided
+    /// and the ones left unchanged since the previous merge
+    pub fn copy_add_paths(
+        &self,
+        mod_dir: &ModDir,
+        previous: Option<&MergeState>,
+        state: &mut MergeState,
+    ) -> Result<()> {
         for add_path in self.add_paths.iter() {
             let source_path = PathBuf::from(&mod_dir.path).join(add_path);
             if source_path.exists() {
                 let destpath = PathBuf::from(&self.root_dir.path).join(add_path);
-                if !destpath.exists() {
-                    fs::create_dir_all(&destpath)?;
+                if !destpath.exists()
+                    && !skip_in_dry_run(self.dry_run, format!("create {}", destpath.display()))
+                {
+                    fs::create_dir_all(&destpath).with_path(&destpath)?;
                 }
-                for entry in fs::read_dir(&source_path)? {
-                    let entry = entry?;
+                for entry in fs::read_dir(&source_path).with_path(&source_path)? {
+                    let entry = entry.with_path(&source_path)?;
                     let path: PathBuf = entry.path();
                     if let Some(filename) = path.file_name() {
                         let dest_file = destpath.join(filename);
-                        fs::copy(&path, &dest_file)?;
+                        let key = format!("{}/{}", add_path, filename.to_string_lossy());
+                        if state.add_files.contains_key(&key) {
+                            continue;
+                        }
+                        let output = OutputFile {
+                            path: key.clone(),
+                            hash: input_file_hash(&path, previous, state)?,
+                            source: mod_dir.name(),
+                        };
+                        let unchanged = previous
+                            .and_then(|x| x.add_files.get(&key))
+                            .is_some_and(|x| x.hash == output.hash && dest_file.exists());
+                        if !unchanged {
+                            self.write_output(&dest_file, &fs::read(&path).with_path(&path)?)?;
+                        }
+                        state.add_files.insert(key, output);
                     } else {
                         eprintln!("ERROR: Invalid file name: {:?}", path);
-                    
-                    }
-                }
-            }
-        }
-
-        Ok(())
-    }
-
-    pub fn copy_from_data_path_to_new_data_path(&self, mod_dir: &ModDir) -> io::Result<()> {
-        //Copy from romfs/asset/data to data in order to protect the user from bricking
-        //the game save file by loading raw files
-        if !mod_dir.data_path.exists() {
-            return Ok(());
-        }
-        println!("{}", &mod_dir.data_path.display());
-        for entry in fs::read_dir(&mod_dir.data_path)? {
-            let entry = entry?;
-            let path: PathBuf = entry.path();
-            if path.is_file() && AocHash::new(&path, self.config.clone()).is_valid() {
-                if let Some(filename) = path.file_name() {
-                    let dest_file = mod_dir.new_data_path.join(filename);
-                    if !move_file(&path, &dest_file) {
-                        eprintln!("ERROR: Failed to move file: {:?} -> {}", path, dest_file.display());
                     }
                 }
-            } else {
-                eprintln!("ERROR: Invalid file name: {:?}", path);
             }
         }
-        fs::remove_dir_all(&mod_dir.data_path)?;
-        // create_dir_no_check(&mod_dir.data_path);
 
         Ok(())
     }
@@ -268,23 +707,33 @@ impl ModMerger {
     pub fn update_aoc_hashes_from_modpath(
         &mut self,
         mod_path: ModDir,
-    ) -> io::Result<()> {
-        if let Ok(entries) = fs::read_dir(&mod_path.new_data_path) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.is_file() {
-                        let aoc_hash = AocHash::new(&path, self.config.clone());
-                        if let Some(rdb_name) = &aoc_hash.rdb_name {
-                            if !self.aoc_hashes.contains_key(rdb_name) {
-                                self.aoc_hashes.insert(rdb_name.to_string(), Vec::new());
-                            }
-                            if let Some(v) = self.aoc_hashes.get_mut(rdb_name) {
+    ) -> Result<()> {
+        let mod_name = mod_path.name();
+        for data_dir in [&mod_path.new_data_path, &mod_path.data_path] {
+            let Ok(entries) = fs::read_dir(data_dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_file() {
+                    let aoc_hash = self.aoc_hash(&path);
+                    if let Some(rdb_name) = &aoc_hash.rdb_name {
+                        if !self.aoc_hashes.contains_key(rdb_name) {
+                            self.aoc_hashes.insert(rdb_name.to_string(), Vec::new());
+                        }
+                        let pinned = aoc_hash
+                            .as_u32()
+                            .is_ok_and(|ktid| self.overrides.get(&ktid) == Some(&mod_name));
+                        if let Some(v) = self.aoc_hashes.get_mut(rdb_name) {
+                            // The first file of a KTID wins, put the pinned one in front
+                            if pinned {
+                                v.insert(0, aoc_hash);
+                            } else {
                                 v.push(aoc_hash);
                             }
-                        } else {
-                            eprintln!("ERROR: Invalid hash, no rdb found: {:?}", aoc_hash);
                         }
+                    } else {
+                        eprintln!("ERROR: Invalid hash, no rdb found: {:?}", aoc_hash);
                     }
                 }
             }
@@ -307,15 +756,6 @@ impl ModMerger {
     //     Ok(())
     // }
 
-    pub fn get_rdb_name(&self, hash: &AocHash) -> Option<String> {
-        for (rdb_name, hashes) in self.config.hashes.iter() {
-            if hashes.contains(&hash.hash) {
-                return Some(rdb_name.to_string());
-            }
-        }
-        None
-    }
-
     pub fn is_valid_mod_dir<P: AsRef<Path>>(&self, path: P) -> bool {
         let p = PathBuf::from(path.as_ref());
         if let Some(name) = p.file_name() {
@@ -323,23 +763,24 @@ impl ModMerger {
                 return false;
             }
         }
-        if p.parent().unwrap().starts_with("#") {
+        if p.parent().is_some_and(|x| x.starts_with("#")) {
             return false;
         }
         p.exists() && p.is_dir()
     }
+}
 
-    pub fn prepare_master_dir(&mut self) -> io::Result<PathBuf> {
-        let mut p = PathBuf::from(&self.cwd_dir);
-        p.push(&self.root_mod_name);
-        if p.exists() {
-            std::fs::remove_dir_all(&p)?;
-        }
-        p.push("romfs/asset/data");
-        std::fs::create_dir_all(&p)?;
-        p.pop();
-        Ok(p)
-    }
+/// Content hash of an input, only read if its size or modification time changed since the previous merge
+fn input_file_hash<P: AsRef<Path>>(
+    path: P,
+    previous: Option<&MergeState>,
+    state: &mut MergeState,
+) -> Result<String> {
+    let key = path.as_ref().to_string_lossy().to_string();
+    let input = InputFile::new(&path, previous.and_then(|x| x.inputs.get(&key)))?;
+    let hash = input.hash.clone();
+    state.inputs.insert(key, input);
+    Ok(hash)
 }
 
 #[derive(Clone)]
@@ -356,47 +797,16 @@ impl AocHash {
         let rdb_name = config.hashes_rev.get(&hash).map(|x| x.to_string());
         Self {
             path: p,
-            hash: hash,
-            rdb_name: rdb_name,
-        }
-    }
-
-    pub fn copy_if_needed(&mut self) -> io::Result<()> {
-        if !Path::new(&self.path.full_path).exists() {
-            return Err(ioErr::new(
-                ErrKind::NotFound,
-                format!("File not found: {}", &self.path.full_path),
-            ));
-        }
-        let mut new_path = PathBuf::from(&self.path.full_path);
-        new_path.pop();
-        new_path.push(format!("{}.file", &self.as_hex_str()));
-        if !new_path.exists() {
-            fs::rename(&self.path.full_path, &new_path)?;
-            self.path = Pathlib::new(&new_path);
+            hash,
+            rdb_name,
         }
-        Ok(())
     }
 
-    pub fn is_valid(&self) -> bool {
-        if self.rdb_name.is_none() {
-            return false;
-        }
-        if self.hash.starts_with("0x") {
-            if self.hash[2..].len() != 8 {
-                return false;
-            }
-            return u32::from_str_radix(&self.hash[2..], 16).is_ok();
-        }
-        if self.hash.len() != 8 {
-            return false;
-        }
-        u32::from_str_radix(&self.hash, 16).is_ok()
-    }
-
-    pub fn as_u32(&self) -> io::Result<u32> {
-        u32::from_str_radix(&self.hash, 16)
-            .map_err(|_| ioErr::new(ErrKind::InvalidData, "Invalid hash"))
+    pub fn as_u32(&self) -> Result<u32> {
+        u32::from_str_radix(&self.hash, 16).map_err(|_| Error::Parse {
+            path: Some(PathBuf::from(&self.path.full_path)),
+            message: "the file name isn't a KTID (0x12345678.file)".to_string(),
+        })
     }
 
     pub fn as_hex_str(&self) -> String {
@@ -415,3 +825,94 @@ impl fmt::Debug for AocHash {
             .finish()
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::rdb::{tests::sample_rdb, IdrkEntry};
+
+    /// Vanilla romfs holding the sample RDB as CharacterEditor.rdb, and mods replacing the given KTIDs
+    pub(crate) fn merger(name: &str, mods: &[(&str, &[u32])]) -> ModMerger {
+        let dir = std::env::temp_dir().join(format!("rdb_tool_{}_{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        let asset_dir = dir.join("romfs/asset");
+        fs::create_dir_all(&asset_dir).unwrap();
+        fs::write(asset_dir.join("CharacterEditor.rdb"), sample_rdb()).unwrap();
+        for (mod_name, ktids) in mods {
+            let data_dir = dir.join("mods").join(mod_name).join("romfs/asset/data");
+            fs::create_dir_all(&data_dir).unwrap();
+            for ktid in ktids.iter() {
+                fs::write(data_dir.join(format!("0x{:08x}.file", ktid)), mod_name).unwrap();
+            }
+        }
+
+        let mut config = AocConfig {
+            romfs: asset_dir.parent().unwrap().to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        for ktid in ["11111111", "22222222", "33333333"] {
+            config.hashes_rev.insert(ktid.to_string(), "CharacterEditor.rdb".to_string());
+        }
+        ModMerger::new_with_config(Some(dir.join("mods")), config)
+    }
+
+    #[test]
+    fn file_conflicts() {
+        let mut merger = merger(
+            "conflicts",
+            &[("modA", &[0x11111111]), ("modB", &[0x11111111]), ("modC", &[0x33333333])],
+        );
+        let mods = Path::new(&merger.cwd_dir).to_path_buf();
+        fs::write(
+            mods.join(LOAD_ORDER_FILE),
+            "[[mods]]\nname = \"modB\"\n[[mods]]\nname = \"modA\"\n[[mods]]\nname = \"modC\"\n",
+        )
+        .unwrap();
+        for mod_name in ["modA", "modB", "modC"] {
+            merger.mods_dirs.push(ModDir::new(mods.join(mod_name), Vec::new(), false));
+        }
+        merger.apply_load_order().unwrap();
+
+        let conflicts = merger.find_file_conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.kind, ConflictKind::File);
+        assert_eq!(conflict.ktid, 0x11111111);
+        assert_eq!(conflict.rdb_name, "CharacterEditor.rdb");
+        assert_eq!(conflict.name, "foo.g1t");
+        assert_eq!(conflict.mods, vec!["modB", "modA"]);
+        assert_eq!(conflict.winner, "modA");
+
+        // Pinned in the load order, the lower priority mod wins
+        merger.overrides.insert(0x11111111, "modB".to_string());
+        assert_eq!(merger.find_file_conflicts().unwrap()[0].winner, "modB");
+
+        fs::remove_dir_all(mods.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn incremental_merge() {
+        let merger = merger("incremental", &[("modA", &[0x11111111]), ("modC", &[0x33333333])]);
+        let config = merger.config.as_ref().clone();
+        let mods = PathBuf::from(&merger.cwd_dir);
+        let merge = || ModMerger::new_with_config(Some(&mods), config.clone()).process_mods().unwrap();
+        let output = mods.join(MERGED_MOD_NAME).join("romfs/asset/data/0x11111111.file");
+        let state_path = mods.join(MERGED_MOD_NAME).join(MERGE_STATE_FILE);
+
+        merge();
+        let wrapped = fs::read(&output).unwrap();
+        assert!(IdrkEntry::is_idrk(&wrapped));
+        let state = MergeState::open(&state_path).unwrap();
+        assert!(state.inputs.keys().any(|x| x.ends_with("0x11111111.file")));
+
+        // The RDB inputs didn't change, but one of its outputs is gone
+        fs::remove_file(&output).unwrap();
+        merge();
+        assert_eq!(fs::read(&output).unwrap(), wrapped);
+        assert_eq!(MergeState::open(&state_path).unwrap(), state);
+
+        fs::remove_dir_all(mods.parent().unwrap()).unwrap();
+    }
+}
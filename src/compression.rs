@@ -0,0 +1,259 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+
+use crate::{
+    error::{Error, Result},
+    rdb::RdbFlags,
+};
+
+/// Decompressed size of every chunk but the last one
+pub const CHUNK_SIZE: usize = 0x10000;
+/// Chunks and the chunk table are padded to this boundary
+const CHUNK_ALIGN: usize = 0x80;
+
+/// Compression of a RDB payload, as described by the `RdbFlags` bits.
+///
+/// Both codecs use the same KT chunked layout:
+/// ```text
+/// u32 chunk_size
+/// u32 chunk_count
+/// u32 decompressed_size
+/// u32 chunk_sizes[chunk_count]   // compressed size of every chunk, prefix included
+/// (padding to 0x80)
+/// chunk_count times:
+///     u32 compressed_size
+///     u8  data[compressed_size]  // zlib stream or raw LZ4 block
+///     (padding to 0x80)
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Zlib,
+    Lz4,
+}
+
+impl Compression {
+    /// Returns `None` for the encrypted combination, where both bits are set
+    pub fn from_flags(flags: &RdbFlags) -> Option<Self> {
+        flags.encoding().compression()
+    }
+
+    pub fn apply_to_flags(&self, flags: &mut RdbFlags) {
+        flags.set_zlib_compressed(*self == Compression::Zlib);
+        flags.set_lz4_compressed(*self == Compression::Lz4);
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "zlib" => Ok(Compression::Zlib),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(format!("Unknown compression '{}', expected none, zlib or lz4", s)),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Zlib => write!(f, "zlib"),
+            Compression::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+fn invalid_data<E: ToString>(e: E) -> Error {
+    Error::parse(e.to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data(format!("Compressed stream truncated at 0x{:x}", offset)))
+}
+
+fn align(offset: usize) -> usize {
+    (offset + CHUNK_ALIGN - 1) & !(CHUNK_ALIGN - 1)
+}
+
+fn pad(buffer: &mut Vec<u8>) {
+    buffer.resize(align(buffer.len()), 0);
+}
+
+pub fn decompress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    if compression == Compression::None {
+        return Ok(data.to_vec());
+    }
+
+    let chunk_size = read_u32(data, 0)? as usize;
+    let chunk_count = read_u32(data, 4)? as usize;
+    let decompressed_size = read_u32(data, 8)? as usize;
+
+    let mut output = Vec::with_capacity(decompressed_size);
+    let mut offset = align(0xC + chunk_count * 4);
+
+    for _ in 0..chunk_count {
+        let compressed_size = read_u32(data, offset)? as usize;
+        let chunk = data
+            .get(offset + 4..offset + 4 + compressed_size)
+            .ok_or_else(|| invalid_data(format!("Chunk at 0x{:x} is out of bounds", offset)))?;
+        let remaining = decompressed_size
+            .checked_sub(output.len())
+            .ok_or_else(|| invalid_data(format!("Chunk at 0x{:x} is past the decompressed size", offset)))?;
+        let expected = chunk_size.min(remaining);
+        let start = output.len();
+
+        match compression {
+            Compression::Zlib => {
+                // One more byte than expected, to catch chunks inflating past their size
+                ZlibDecoder::new(chunk)
+                    .take(expected as u64 + 1)
+                    .read_to_end(&mut output)
+                    .map_err(invalid_data)?;
+            }
+            Compression::Lz4 => {
+                let block = lz4_flex::block::decompress(chunk, expected).map_err(invalid_data)?;
+                output.extend_from_slice(&block);
+            }
+            Compression::None => unreachable!(),
+        }
+
+        if output.len() - start != expected {
+            return Err(invalid_data(format!(
+                "Chunk at 0x{:x} decompressed to {} bytes, expected {}",
+                offset,
+                output.len() - start,
+                expected
+            )));
+        }
+
+        offset = align(offset + 4 + compressed_size);
+    }
+
+    if output.len() != decompressed_size {
+        return Err(invalid_data(format!(
+            "Decompressed {} bytes, expected {}",
+            output.len(),
+            decompressed_size
+        )));
+    }
+
+    Ok(output)
+}
+
+pub fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    if compression == Compression::None {
+        return Ok(data.to_vec());
+    }
+
+    let chunks: Vec<Vec<u8>> = data
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| match compression {
+            Compression::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(chunk)?;
+                encoder.finish()
+            }
+            Compression::Lz4 => Ok(lz4_flex::block::compress(chunk)),
+            Compression::None => unreachable!(),
+        })
+        .collect::<io::Result<_>>()?;
+
+    let mut output = Vec::new();
+    output.extend_from_slice(&(CHUNK_SIZE as u32).to_le_bytes());
+    output.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    for chunk in &chunks {
+        output.extend_from_slice(&(chunk.len() as u32 + 4).to_le_bytes());
+    }
+    pad(&mut output);
+
+    for chunk in &chunks {
+        output.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        output.extend_from_slice(chunk);
+        pad(&mut output);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 123).map(|i| (i % 251) as u8).collect();
+
+        for compression in [Compression::None, Compression::Zlib, Compression::Lz4] {
+            let compressed = compress(&data, compression).unwrap();
+            assert_eq!(decompress(&compressed, compression).unwrap(), data);
+        }
+    }
+
+    /// KT chunked stream built by hand: header, chunk table, then every chunk at a 0x80 boundary
+    fn chunked(chunk_size: u32, decompressed_size: u32, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for x in [chunk_size, chunks.len() as u32, decompressed_size] {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        for chunk in chunks {
+            bytes.extend_from_slice(&(chunk.len() as u32 + 4).to_le_bytes());
+        }
+        for chunk in chunks {
+            bytes.resize((bytes.len() + 0x7f) & !0x7f, 0);
+            bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(chunk);
+        }
+        bytes.resize((bytes.len() + 0x7f) & !0x7f, 0);
+        bytes
+    }
+
+    // Zlib stream with a single stored block holding "hello"
+    const ZLIB_HELLO: &[u8] = &[
+        0x78, 0x01, 0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x06, 0x2c, 0x02, 0x15,
+    ];
+
+    #[test]
+    fn fixed_layout() {
+        // LZ4 blocks made of literals only: the token holds their count in its high nibble
+        let lz4 = chunked(3, 5, &[&[0x30, b'h', b'e', b'l'], &[0x20, b'l', b'o']]);
+        assert_eq!(&lz4[..0x14], &[3, 0, 0, 0, 2, 0, 0, 0, 5, 0, 0, 0, 8, 0, 0, 0, 7, 0, 0, 0]);
+        assert_eq!(&lz4[0x80..0x88], &[4, 0, 0, 0, 0x30, b'h', b'e', b'l']);
+        assert_eq!(lz4.len(), 0x180);
+        assert_eq!(decompress(&lz4, Compression::Lz4).unwrap(), b"hello");
+
+        let zlib = chunked(0x10000, 5, &[ZLIB_HELLO]);
+        assert_eq!(decompress(&zlib, Compression::Zlib).unwrap(), b"hello");
+        assert_eq!(
+            compress(b"hello", Compression::Lz4).unwrap(),
+            chunked(0x10000, 5, &[&[0x50, b'h', b'e', b'l', b'l', b'o']])
+        );
+    }
+
+    #[test]
+    fn malformed_chunks() {
+        // Chunk inflating past the declared size
+        let oversized = chunked(0x10000, 3, &[ZLIB_HELLO]);
+        assert!(decompress(&oversized, Compression::Zlib).is_err());
+        let oversized = chunked(3, 3, &[&[0x50, b'h', b'e', b'l', b'l', b'o']]);
+        assert!(decompress(&oversized, Compression::Lz4).is_err());
+
+        // Chunk cut in the middle, and a stream with a missing chunk
+        let zlib = chunked(0x10000, 5, &[ZLIB_HELLO]);
+        assert!(decompress(&zlib[..0x88], Compression::Zlib).is_err());
+        let lz4 = chunked(3, 5, &[&[0x30, b'h', b'e', b'l'], &[0x20, b'l', b'o']]);
+        assert!(decompress(&lz4[..0x100], Compression::Lz4).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    params::RdbParams,
+    rdb::{Rdb, RdbEntry, RdbFlags},
+};
+
+/// Entries added, removed and modified between two RDBs, matched by `file_ktid`
+#[derive(Debug, Default, Serialize)]
+pub struct RdbDiff {
+    pub added: Vec<EntrySummary>,
+    pub removed: Vec<EntrySummary>,
+    pub modified: Vec<EntryDiff>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntrySummary {
+    pub ktid: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryDiff {
+    pub ktid: String,
+    pub name: String,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl FieldChange {
+    fn new<T: fmt::Display>(field: &str, old: T, new: T) -> Self {
+        Self {
+            field: field.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        }
+    }
+}
+
+impl EntrySummary {
+    fn new(entry: &RdbEntry) -> Self {
+        Self {
+            ktid: format!("0x{:08x}", entry.file_ktid),
+            name: String::from_utf8_lossy(&entry.name).to_string(),
+        }
+    }
+}
+
+impl RdbDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+pub fn diff_rdbs(old: &Rdb, new: &Rdb) -> RdbDiff {
+    let new_entries: HashMap<u32, &RdbEntry> = new.entries.iter().map(|x| (x.file_ktid, x)).collect();
+    let old_entries: HashMap<u32, &RdbEntry> = old.entries.iter().map(|x| (x.file_ktid, x)).collect();
+
+    let mut diff = RdbDiff::default();
+    for entry in &old.entries {
+        match new_entries.get(&entry.file_ktid) {
+            Some(new_entry) => {
+                let changes = diff_entries(entry, new_entry);
+                if !changes.is_empty() {
+                    diff.modified.push(EntryDiff {
+                        ktid: format!("0x{:08x}", entry.file_ktid),
+                        name: String::from_utf8_lossy(&new_entry.name).to_string(),
+                        changes,
+                    });
+                }
+            }
+            None => diff.removed.push(EntrySummary::new(entry)),
+        }
+    }
+    diff.added = new
+        .entries
+        .iter()
+        .filter(|x| !old_entries.contains_key(&x.file_ktid))
+        .map(EntrySummary::new)
+        .collect();
+
+    diff
+}
+
+/// Every field that differs between two versions of the same entry
+pub fn diff_entries(old: &RdbEntry, new: &RdbEntry) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+
+    if old.name != new.name {
+        changes.push(FieldChange::new(
+            "name",
+            String::from_utf8_lossy(&old.name),
+            String::from_utf8_lossy(&new.name),
+        ));
+    }
+    if old.file_size != new.file_size {
+        changes.push(FieldChange::new(
+            "file_size",
+            format!("0x{:x}", old.file_size),
+            format!("0x{:x}", new.file_size),
+        ));
+    }
+    if old.entry_type != new.entry_type {
+        changes.push(FieldChange::new("entry_type", old.entry_type, new.entry_type));
+    }
+    if old.type_info_ktid != new.type_info_ktid {
+        changes.push(FieldChange::new(
+            "type_info_ktid",
+            format!("0x{:08x}", old.type_info_ktid),
+            format!("0x{:08x}", new.type_info_ktid),
+        ));
+    }
+    for (field, old_value, new_value) in [("unk", old.unk, new.unk), ("unk2", old.unk2, new.unk2)] {
+        if old_value != new_value {
+            changes.push(FieldChange::new(
+                field,
+                format!("0x{:x}", old_value),
+                format!("0x{:x}", new_value),
+            ));
+        }
+    }
+    changes.extend(diff_flags(&old.flags, &new.flags));
+    if old.unk_content != new.unk_content {
+        changes.extend(diff_params(&old.params(), &new.params()));
+    }
+
+    changes
+}
+
+fn diff_flags(old: &RdbFlags, new: &RdbFlags) -> Vec<FieldChange> {
+    let bits = [
+        ("flags.external", old.external(), new.external()),
+        ("flags.internal", old.internal(), new.internal()),
+        ("flags.zlib_compressed", old.zlib_compressed(), new.zlib_compressed()),
+        ("flags.lz4_compressed", old.lz4_compressed(), new.lz4_compressed()),
+    ];
+    let unknown = [
+        ("flags.unk", old.unk() as u32, new.unk() as u32),
+        ("flags.unk2", old.unk2() as u32, new.unk2() as u32),
+        ("flags.unk3", old.unk3() as u32, new.unk3() as u32),
+    ];
+
+    bits.iter()
+        .filter(|(_, old, new)| old != new)
+        .map(|(field, old, new)| FieldChange::new(field, old, new))
+        .chain(
+            unknown
+                .iter()
+                .filter(|(_, old, new)| old != new)
+                .map(|(field, old, new)| {
+                    FieldChange::new(field, format!("0x{:x}", old), format!("0x{:x}", new))
+                }),
+        )
+        .collect()
+}
+
+fn diff_params(old: &RdbParams, new: &RdbParams) -> Vec<FieldChange> {
+    match (old, new) {
+        (RdbParams::Parsed(old_block), RdbParams::Parsed(new_block)) => {
+            let mut changes = Vec::new();
+            if old_block.unk != new_block.unk {
+                changes.push(FieldChange::new(
+                    "params.unk",
+                    format!("0x{:08x}", old_block.unk),
+                    format!("0x{:08x}", new_block.unk),
+                ));
+            }
+
+            let describe = |x: Option<&crate::params::RdbParam>| match x {
+                Some(param) => format!("0x{:016x} (unk: 0x{:08x})", param.value, param.unk),
+                None => "-".to_string(),
+            };
+            let mut ktids: Vec<u32> = old_block.params.iter().map(|x| x.param_ktid).collect();
+            ktids.extend(
+                new_block
+                    .params
+                    .iter()
+                    .map(|x| x.param_ktid)
+                    .filter(|x| !old_block.params.iter().any(|p| p.param_ktid == *x)),
+            );
+            for ktid in ktids {
+                let old_param = old_block.params.iter().find(|x| x.param_ktid == ktid);
+                let new_param = new_block.params.iter().find(|x| x.param_ktid == ktid);
+                if old_param != new_param {
+                    changes.push(FieldChange::new(
+                        &format!("params[0x{:08x}]", ktid),
+                        describe(old_param),
+                        describe(new_param),
+                    ));
+                }
+            }
+            changes
+        }
+        _ => vec![FieldChange::new(
+            "unk_content",
+            old.to_string().trim_end(),
+            new.to_string().trim_end(),
+        )],
+    }
+}
+
+impl fmt::Display for RdbDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.added {
+            writeln!(f, "+ {} {}", entry.ktid, entry.name)?;
+        }
+        for entry in &self.removed {
+            writeln!(f, "- {} {}", entry.ktid, entry.name)?;
+        }
+        for entry in &self.modified {
+            writeln!(f, "~ {} {}", entry.ktid, entry.name)?;
+            for change in &entry.changes {
+                writeln!(f, "    {}: {} -> {}", change.field, change.old, change.new)?;
+            }
+        }
+        write!(
+            f,
+            "{} added, {} removed, {} modified",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::tests::sample_rdb;
+
+    #[test]
+    fn diff_sample() {
+        let old = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        let mut new = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        assert!(diff_rdbs(&old, &new).is_empty());
+
+        new.entries[0].file_size = 0x10;
+        new.entries[0].flags.set_lz4_compressed(true);
+        new.entries.remove(1);
+        let diff = diff_rdbs(&old, &new);
+
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].ktid, "0x22222222");
+        let fields: Vec<_> = diff.modified[0].changes.iter().map(|x| x.field.as_str()).collect();
+        assert_eq!(fields, vec!["file_size", "flags.lz4_compressed"]);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading, writing or listing a file failed
+    Io { path: Option<PathBuf>, source: io::Error },
+    /// Malformed input: a RDB, a TOML or JSON file, a KTID, a file name...
+    Parse { path: Option<PathBuf>, message: String },
+    /// No valid romfs dump is configured
+    RomfsNotFound(String),
+    /// A KTID missing from the RDB it is looked up in
+    UnknownKtid { ktid: u32, rdb: Option<PathBuf> },
+    /// An entry an operation can't handle because of its type or encoding
+    UnsupportedEntryType { ktid: u32, message: String },
+    /// Arguments or mods that can't be processed as they are
+    Invalid(String),
+    /// Something went wrong while processing a mod
+    Mod { name: String, source: Box<Error> },
+}
+
+impl Error {
+    pub fn parse<S: Into<String>>(message: S) -> Self {
+        Error::Parse {
+            path: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid<S: Into<String>>(message: S) -> Self {
+        Error::Invalid(message.into())
+    }
+
+    /// The I/O error behind this one, if any
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            Error::Io { source, .. } => Some(source.kind()),
+            Error::Mod { source, .. } => source.io_kind(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "{}", source),
+            Error::Parse { path: Some(path), message } => {
+                write!(f, "Unable to parse {}: {}", path.display(), message)
+            }
+            Error::Parse { path: None, message } => write!(f, "{}", message),
+            Error::RomfsNotFound(message) => write!(f, "Invalid romfs path! {}", message),
+            Error::UnknownKtid { ktid, rdb: Some(rdb) } => {
+                write!(f, "KTID 0x{:08x} not found in {}", ktid, rdb.display())
+            }
+            Error::UnknownKtid { ktid, rdb: None } => write!(f, "KTID 0x{:08x} not found in the RDB.", ktid),
+            Error::UnsupportedEntryType { ktid, message } => write!(f, "Entry 0x{:08x}: {}", ktid, message),
+            Error::Invalid(message) => write!(f, "{}", message),
+            Error::Mod { name, source } => write!(f, "In mod {}: {}", name, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Mod { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+impl From<binread::Error> for Error {
+    fn from(e: binread::Error) -> Self {
+        match e {
+            binread::Error::Io(source) => Error::Io { path: None, source },
+            e => Error::parse(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            Error::Io {
+                path: None,
+                source: e.into(),
+            }
+        } else {
+            Error::parse(e.to_string())
+        }
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::parse(e.to_string())
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::parse(e.to_string())
+    }
+}
+
+/// For the `BinRead`/`BinWrite` impls, which have to return `io::Error`s
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io { path: None, source } => source,
+            e => io::Error::new(e.io_kind().unwrap_or(io::ErrorKind::InvalidData), e.to_string()),
+        }
+    }
+}
+
+/// Attach the file or the mod an error is about
+pub trait Context<T> {
+    fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T>;
+    fn in_mod(self, name: &str) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn with_path<P: AsRef<Path>>(self, path: P) -> Result<T> {
+        self.map_err(|e| match e.into() {
+            Error::Io { path: None, source } => Error::Io {
+                path: Some(path.as_ref().to_path_buf()),
+                source,
+            },
+            Error::Parse { path: None, message } => Error::Parse {
+                path: Some(path.as_ref().to_path_buf()),
+                message,
+            },
+            e => e,
+        })
+    }
+
+    fn in_mod(self, name: &str) -> Result<T> {
+        self.map_err(|e| match e.into() {
+            e @ Error::Mod { .. } => e,
+            e => Error::Mod {
+                name: name.to_string(),
+                source: Box::new(e),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context() {
+        let e = fs_error().with_path("mods/a/data/0x1.file").in_mod("a").unwrap_err();
+        assert_eq!(e.io_kind(), Some(io::ErrorKind::NotFound));
+        assert!(e.to_string().starts_with("In mod a: mods/a/data/0x1.file: "));
+
+        let e = crate::ktid::ktid("0xnothex").with_path("load_order.toml").unwrap_err();
+        assert!(matches!(e, Error::Parse { path: Some(_), .. }));
+    }
+
+    fn fs_error() -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs::File,
+    io::{BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use binread::{BinRead, BinReaderExt};
+
+use crate::{
+    error::{Context, Error, Result},
+    rdb::{IdrkEntry, Rdb, RdbEntry, RdbStorage},
+};
+
+/// One record of a `.rdx` index, mapping the fdata id used in entry names to the KTID of the container
+#[derive(BinRead, Debug, Clone, Copy)]
+#[br(little)]
+pub struct RdxEntry {
+    pub index: u16,
+    pub unk: u16,
+    pub fdata_ktid: u32,
+}
+
+/// Where the IDRK blob of an entry is stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryLocation {
+    /// Standalone `0x{ktid}.file` in the data directory next to the RDB
+    External(PathBuf),
+    /// Blob stored at `offset` inside a `.fdata` container
+    Internal { fdata: PathBuf, offset: u64, size: u64 },
+}
+
+/// A parsed RDB along with everything needed to locate the data of its entries
+pub struct RdbContainer {
+    pub rdb: Rdb,
+    pub dir: PathBuf,
+    pub stem: String,
+    pub rdx: Vec<RdxEntry>,
+}
+
+impl RdbContainer {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let rdb = Rdb::open(path)?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let stem = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default()
+            .to_string();
+
+        // The index is optional, RDBs with a single container simply use {stem}.fdata
+        let rdx_path = path.with_extension("rdx");
+        let rdx = if rdx_path.exists() {
+            Self::read_rdx(&rdx_path).with_path(&rdx_path)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self { rdb, dir, stem, rdx })
+    }
+
+    fn read_rdx(path: &Path) -> Result<Vec<RdxEntry>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut entries = Vec::new();
+        while reader.stream_position()? + 8 <= len {
+            let entry: RdxEntry = reader.read_le()?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        self.dir.join("data")
+    }
+
+    pub fn fdata_path(&self, fdata_id: u32) -> PathBuf {
+        match self.rdx.iter().find(|x| x.index as u32 == fdata_id) {
+            Some(rdx) => self.dir.join(format!("0x{:08x}.fdata", rdx.fdata_ktid)),
+            None => self.dir.join(format!("{}.fdata", self.stem)),
+        }
+    }
+
+    pub fn locate(&self, entry: &RdbEntry) -> Result<EntryLocation> {
+        if entry.storage() == Some(RdbStorage::External) {
+            return Ok(EntryLocation::External(
+                self.data_dir().join(entry.get_external_path()),
+            ));
+        }
+
+        // Internal entries carry their location in the name: ...@size@offset@fdata_id, the size
+        // coming first like in the `...@size` names of external entries
+        let name = entry.entry_name().unwrap_or_default();
+        match (name.size(), name.extra_hex(0), name.extra_hex(1)) {
+            (Some(size), Some(offset), Some(fdata_id)) => Ok(EntryLocation::Internal {
+                fdata: self.fdata_path(fdata_id as u32),
+                offset,
+                size,
+            }),
+            _ => Err(Error::UnsupportedEntryType {
+                ktid: entry.file_ktid,
+                message: format!("unable to locate the data of {}", name),
+            }),
+        }
+    }
+
+    /// Read the IDRK blob (header and payload) of an entry from its container
+    pub fn read_idrk(&self, entry: &RdbEntry) -> Result<IdrkEntry> {
+        let (path, offset) = match self.locate(entry)? {
+            EntryLocation::External(path) => (path, 0),
+            EntryLocation::Internal { fdata, offset, .. } => (fdata, offset),
+        };
+
+        let mut reader = BufReader::new(File::open(&path).with_path(&path)?);
+        reader.seek(SeekFrom::Start(offset)).with_path(&path)?;
+
+        IdrkEntry::from_reader(&mut reader).map_err(|e| Error::Parse {
+            path: Some(path.clone()),
+            message: format!("no IDRK blob at 0x{:x}: {}", offset, e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::tests::sample_rdb;
+
+    #[test]
+    fn locate() {
+        let rdb = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        let mut entry = rdb.entries[1].clone();
+        entry.flags.set_external(false);
+        entry.flags.set_internal(true);
+        let mut container = RdbContainer {
+            rdb,
+            dir: PathBuf::from("romfs/asset"),
+            stem: "bar".to_string(),
+            rdx: Vec::new(),
+        };
+
+        // R_g1e［bar_baz］@10@20@0
+        let expected = EntryLocation::Internal {
+            fdata: PathBuf::from("romfs/asset/bar.fdata"),
+            offset: 0x20,
+            size: 0x10,
+        };
+        assert_eq!(container.locate(&entry).unwrap(), expected);
+
+        container.rdx.push(RdxEntry {
+            index: 0,
+            unk: 0,
+            fdata_ktid: 0xcafebabe,
+        });
+        assert!(matches!(
+            container.locate(&entry).unwrap(),
+            EntryLocation::Internal { fdata, .. } if fdata == Path::new("romfs/asset/0xcafebabe.fdata")
+        ));
+
+        let external = container.rdb.entries[0].clone();
+        assert_eq!(
+            container.locate(&external).unwrap(),
+            EntryLocation::External(PathBuf::from("romfs/asset/data").join(external.get_external_path()))
+        );
+    }
+}
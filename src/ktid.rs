@@ -1,8 +1,12 @@
+use std::convert::TryFrom;
 use std::fmt;
 use std::path::Path;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct KTID(pub u32);
 
 impl KTID {
@@ -11,9 +15,8 @@ impl KTID {
     }
 
     /// The argument is treated as a path, meaning you need to provide the filename and extension like a regular path.
-    #[allow(dead_code)]
-    pub fn new<P: AsRef<Path>>(name: P) -> Self {
-        KTID::from(name.as_ref())
+    pub fn new<P: AsRef<Path>>(name: P) -> Result<Self> {
+        KTID::try_from(name.as_ref())
     }
 }
 
@@ -23,36 +26,24 @@ impl From<u32> for KTID {
     }
 }
 
-impl From<&str> for KTID {
-    fn from(string: &str) -> Self {
-        ktid(string)
-    }
-}
-
 impl FromStr for KTID {
-    type Err = ();
-    fn from_str(s: &str) -> Result<KTID, ()> {
-        Ok(KTID::from(s))
+    type Err = Error;
+    fn from_str(s: &str) -> Result<KTID> {
+        ktid(s)
     }
 }
 
-impl From<&Path> for KTID {
-    fn from(path: &Path) -> Self {
-        let mut buffer = Vec::<u8>::new();
-        buffer.extend_from_slice("R_".as_bytes());
+impl TryFrom<&Path> for KTID {
+    type Error = Error;
+    fn try_from(path: &Path) -> Result<Self> {
+        let invalid = |what: &str| Error::Parse {
+            path: Some(path.to_path_buf()),
+            message: format!("invalid {}, expected name.extension", what),
+        };
+        let extension = path.extension().and_then(|ext| ext.to_str()).ok_or_else(|| invalid("extension"))?;
+        let stem = path.file_stem().and_then(|x| x.to_str()).ok_or_else(|| invalid("file name"))?;
         // Huehuehue
-        buffer.extend_from_slice(
-            path.extension()
-                .and_then(|ext| ext.to_str())
-                .map(|s| s.as_bytes())
-                .expect(&format!("Invalid extension: \"{:?}\"", path.extension()))
-        );
-        buffer.extend_from_slice("［".as_bytes());
-        // buffer.extend_from_slice(path.file_stem().unwrap().to_str().unwrap().as_bytes());
-        buffer.extend_from_slice(path.file_stem().and_then(|x| x.to_str()).and_then(|s| Some(s.as_bytes())).expect("Invalid file_stem"));
-        buffer.extend_from_slice("］".as_bytes());
-
-        ktid(&String::from_utf8(buffer).expect(&format!("Invalid path: {:?}", path)))
+        ktid(&format!("R_{}［{}］", extension, stem))
     }
 }
 
@@ -62,24 +53,53 @@ impl fmt::Display for KTID {
     }
 }
 
-pub fn ktid(string: &str) -> KTID {
-    if string.starts_with("0x") {
-        KTID(u32::from_str_radix(string.trim_start_matches("0x"), 16).expect("Invalid KTID"))
+/// KTID written as `0x...`, or the hash of a name
+pub fn ktid(string: &str) -> Result<KTID> {
+    if let Some(hex) = string.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+            .map(KTID)
+            .map_err(|e| Error::parse(format!("Invalid KTID '{}': {}", string, e)))
+    } else if string.is_empty() {
+        Err(Error::parse("Empty KTID name"))
+    } else {
+        Ok(KTID(ktid_hash(string, 31)))
+    }
+}
 
+/// KTID given by the user: `0x...`, a file name (`name.extension`, see [`KTID::new`]) or a full name to hash
+pub fn ktid_arg(string: &str) -> Result<KTID> {
+    let path = Path::new(string);
+    if string.starts_with("0x") || string.contains('［') || path.extension().is_none() {
+        ktid(string)
     } else {
-        KTID(ktid_hash(string, 31))
+        KTID::new(path)
     }
 }
 
 pub fn ktid_hash<T: AsRef<[u8]>>(text: T, mut key: i32) -> u32 {
-    let bytes = text.as_ref();
+    let Some((first, rest)) = text.as_ref().split_first() else {
+        return 0;
+    };
 
-    let mut iv = bytes[0] as i32 * 31;
+    let mut iv = *first as i32 * 31;
 
-    for cur_char in &bytes[1..] {
+    for cur_char in rest {
         iv = iv.wrapping_add(31i32.wrapping_mul(key.wrapping_mul((*cur_char as i8) as i32)));
         key = key.wrapping_mul(31);
     }
 
     iv as u32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ktid_args() {
+        assert_eq!(ktid_arg("0x11111111").unwrap(), KTID(0x11111111));
+        assert_eq!(ktid_arg("new_tex.g1t").unwrap(), ktid("R_g1t［new_tex］").unwrap());
+        assert_eq!(ktid_arg("R_g1t［new_tex］").unwrap(), ktid("R_g1t［new_tex］").unwrap());
+        assert_eq!(ktid_arg("TypeInfo::Object::Texture").unwrap(), ktid("TypeInfo::Object::Texture").unwrap());
+    }
+}
@@ -0,0 +1,60 @@
+//! Read, edit and write Koei Tecmo RDB files, and merge Age of Calamity mods.
+//!
+//! This is the library behind the `rdb_tool` executable, for tools that would rather call it
+//! than run the executable.
+//!
+//! - [`Rdb`] is a whole RDB file and [`RdbEntry`] one of its entries, with its [`RdbFlags`].
+//! - [`KTID`] is the hash identifying files and type infos, [`ktid()`] hashes a name.
+//! - [`typeinfo`] is the registry of the known type infos, as `KTID` constants.
+//! - [`ModMerger::ModMerger`] merges every mod of a directory, as the `merge` command does.
+//! - [`Error`] is returned by everything that can fail, with the file or mod it is about.
+//!
+//! ```no_run
+//! use rdb_tool::{ktid, Rdb};
+//!
+//! let rdb = Rdb::open("romfs/asset/CharacterEditor.rdb")?;
+//! if let Some(entry) = rdb.get_entry_by_ktid(&ktid("R_ext［0x12345678］")?) {
+//!     println!("{}", entry.get_resource_file_name().display());
+//! }
+//! # Ok::<(), rdb_tool::Error>(())
+//! ```
+#![allow(non_snake_case, non_camel_case_types)]
+
+/// Romfs location and the AOC hashes cache
+pub mod AocConfig;
+/// Merging a directory of mods into a single one
+pub mod ModMerger;
+/// Compression of the wrapped files
+pub mod compression;
+/// Field by field comparison of two RDBs
+pub mod diff;
+/// Error type of the whole crate
+pub mod error;
+/// `.fdata` containers
+pub mod fdata;
+/// KTID hashes of file and type info names
+pub mod ktid;
+/// Load order manifest of a mods directory
+pub mod load_order;
+/// Three-way merge of RDB entries
+pub mod merge;
+/// State of the last merge, for incremental merges
+pub mod merge_state;
+/// Optional `mod.toml` of a mod folder
+pub mod mod_info;
+/// Names of RDB entries
+pub mod name;
+/// Parameter blocks of RDB entries
+pub mod params;
+/// RDB files, their entries and the IDRK blobs they point to
+pub mod rdb;
+/// Undoing the last merge
+pub mod rollback;
+/// Known type infos, by `TypeInfo::...` path
+pub mod typeinfo;
+/// File system helpers, emulator directory detection and wildcard matching
+pub mod utils;
+
+pub use error::{Error, Result};
+pub use ktid::{ktid, KTID};
+pub use rdb::{EntryType, IdrkEntry, Rdb, RdbEntry, RdbFlags};
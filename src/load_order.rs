@@ -0,0 +1,135 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Context, Error, Result};
+
+/// Name of the load order manifest, next to the mods
+pub const LOAD_ORDER_FILE: &str = "load_order.toml";
+
+const HEADER: &str = "\
+# Age of Calamity mods load order
+# Mods are listed from the lowest to the highest priority, the last one wins conflicts.
+# Set `enabled = false` to skip a mod, and pin the winner of a KTID in [overrides]:
+#   \"0x12345678\" = \"mod folder name\"
+";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoadOrderMod {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadOrder {
+    #[serde(default)]
+    pub mods: Vec<LoadOrderMod>,
+    /// KTID (`0x...`) to the name of the mod that must provide it
+    #[serde(default)]
+    pub overrides: BTreeMap<String, String>,
+}
+
+impl LoadOrder {
+    /// Read the manifest, an empty load order is returned if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        toml::from_str(&fs::read_to_string(path).with_path(path)?).with_path(path)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let toml_str = toml::to_string_pretty(self)?;
+        fs::write(&path, format!("{}\n{}", HEADER, toml_str)).with_path(path)
+    }
+
+    /// Append the mods not listed yet, with the lowest priority left to the ones already there.
+    /// Returns whether any was added.
+    pub fn add_new_mods(&mut self, names: &[String]) -> bool {
+        let mut added = false;
+        for name in names {
+            if !self.mods.iter().any(|x| &x.name == name) {
+                self.mods.push(LoadOrderMod {
+                    name: name.to_string(),
+                    enabled: true,
+                });
+                added = true;
+            }
+        }
+        added
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.mods
+            .iter()
+            .find(|x| x.name == name)
+            .is_none_or(|x| x.enabled)
+    }
+
+    /// Position of a mod in the load order, unlisted mods come last
+    pub fn priority(&self, name: &str) -> usize {
+        self.mods
+            .iter()
+            .position(|x| x.name == name)
+            .unwrap_or(self.mods.len())
+    }
+
+    /// Pinned winners by KTID
+    pub fn overrides(&self) -> Result<HashMap<u32, String>> {
+        self.overrides
+            .iter()
+            .map(|(ktid, mod_name)| {
+                u32::from_str_radix(ktid.trim_start_matches("0x"), 16)
+                    .map(|ktid| (ktid, mod_name.to_string()))
+                    .map_err(|_| {
+                        Error::parse(format!("Invalid KTID '{}' in the load order overrides", ktid))
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_update() {
+        let mut load_order: LoadOrder = toml::from_str(
+            r#"
+            [[mods]]
+            name = "b"
+
+            [[mods]]
+            name = "a"
+            enabled = false
+
+            [overrides]
+            "0x22222222" = "b"
+            "#,
+        )
+        .unwrap();
+
+        assert!(!load_order.is_enabled("a"));
+        assert!(load_order.is_enabled("c"));
+        assert_eq!(load_order.priority("a"), 1);
+        assert_eq!(load_order.overrides().unwrap()[&0x22222222], "b");
+
+        assert!(load_order.add_new_mods(&["a".to_string(), "c".to_string()]));
+        assert!(!load_order.add_new_mods(&["c".to_string()]));
+        assert_eq!(load_order.priority("c"), 2);
+
+        let saved = toml::to_string_pretty(&load_order).unwrap();
+        assert_eq!(toml::from_str::<LoadOrder>(&saved).unwrap(), load_order);
+    }
+}
@@ -1,38 +1,95 @@
-#![allow(dead_code)]
-#![allow(non_snake_case, non_camel_case_types)]
-use binread::{io::Cursor, BinRead};
-use binwrite::BinWrite;
-use utils::copy_dirs;
-use ModMerger::AocHash;
-mod ModMerger;
-mod utils;
-use std::{env, io::{self, Read, Stdin}, os::windows::process, path::PathBuf, sync::Arc};
-mod AocConfig;
-mod rdb;
-use rdb::Rdb;
-mod ktid;
-use ktid::ktid;
-mod typeinfo;
+#![allow(non_snake_case)]
+use rdb_tool::{
+    compression::Compression,
+    diff,
+    error::{Context, Error, Result},
+    fdata::RdbContainer,
+    ktid::{ktid, ktid_arg},
+    rdb::{EntryType, IdrkEntry, Rdb, RdbEntry},
+    rollback,
+    utils::{self, copy_dirs},
+    AocConfig::{self, normalize_path, ConfigOptions},
+    ModMerger::{self, AocHash},
+};
+use std::{
+    collections::HashSet,
+    env,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use structopt::StructOpt;
 
-use crate::AocConfig::normalize_path;
-
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "RdbTool",
-    about = "Simple command-line tool to manipulate RDB files."
+    about = "Simple command-line tool to manipulate RDB files and merge Age of Calamity mods."
 )]
 struct Opt {
+    /// Romfs dump to use instead of the configured one (or set AOC_ROMFS). Repeat it to add the
+    /// update and DLC romfs after the base one, later ones shadow earlier ones
+    #[structopt(long = "romfs", global = true, parse(from_os_str), number_of_values = 1)]
+    romfs_path: Vec<PathBuf>,
+    /// Never open a dialog, fail if no valid romfs path is found
+    #[structopt(long, global = true)]
+    no_gui: bool,
+    /// Without a subcommand, merge like the original `AOC_mods_merger.exe <mods_directory> [-o out]`
+    #[structopt(flatten)]
+    merge: Merge,
     #[structopt(subcommand)]
-    cmd: Command,
+    cmd: Option<Command>,
 }
 
 #[derive(Debug, StructOpt)]
 enum Command {
+    /// Merge every mod found in a directory (default when no subcommand is given)
+    Merge(Merge),
     /// Patch a RDB file
     Patch(Patch),
     /// Output relevant informations about a RDB entry
     Print(Print),
+    /// Extract every file referenced by a RDB
+    Extract(Extract),
+    /// Check that every RDB of the romfs (and its layers, see --romfs) is written back byte for byte
+    Verify,
+    /// Print or edit the parameters of a RDB entry
+    Params(Params),
+    /// Inspect, wrap or unwrap IDRK files
+    Idrk(Idrk),
+    /// Add new files to a RDB
+    Add(Add),
+    /// Remove entries from a RDB
+    Remove(Remove),
+    /// Compare the entries of two RDB files
+    Diff(Diff),
+    /// Undo the last merge, restoring the previous merged mod
+    Rollback(Rollback),
+}
+
+#[derive(Debug, StructOpt)]
+struct Merge {
+    #[structopt(
+        parse(from_os_str),
+        help = "Path to directory containing all mods (defaults to the current directory)"
+    )]
+    pub job_path: Option<PathBuf>,
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Optional argument where to copy the merged mod directory"
+    )]
+    pub output: Option<PathBuf>,
+    #[structopt(
+        long,
+        default_value = "none",
+        help = "Compression of the generated files (none, zlib or lz4)"
+    )]
+    pub compression: Compression,
+    #[structopt(long, help = "Exit with an error before writing anything if mods conflict")]
+    pub fail_on_conflict: bool,
+    #[structopt(long, help = "Print what would be moved, written and deleted without touching any file")]
+    pub dry_run: bool,
 }
 
 #[derive(Debug, StructOpt)]
@@ -47,67 +104,226 @@ struct Patch {
         help = "Directory where the files to patch are located"
     )]
     pub data_path: PathBuf,
+    #[structopt(
+        long,
+        default_value = "none",
+        help = "Compression of the generated files (none, zlib or lz4)"
+    )]
+    pub compression: Compression,
 }
 
 #[derive(Debug, StructOpt)]
 struct Print {
     #[structopt(parse(from_os_str), help = "Path to the RDB file")]
     pub path: PathBuf,
-    #[structopt(help = "The KTID you would like to print")]
+    #[structopt(help = "The KTID (0x...) or file name (name.ext) you would like to print")]
     pub ktid: String,
 }
 
-fn patch_rdb(args: &Patch) -> io::Result<()> {
-    let config = Arc::new(AocConfig::AocConfig::safe_new()?);
-    let mut rdb =
-        Rdb::open(&args.path).expect(&format!("Failed to open RDB file: {:?}", args.path));
+#[derive(Debug, StructOpt)]
+struct Extract {
+    #[structopt(parse(from_os_str), help = "Path to the RDB file")]
+    pub path: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        default_value = "extracted",
+        help = "Directory where the extracted files are written"
+    )]
+    pub out_path: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct Params {
+    #[structopt(parse(from_os_str), help = "Path to the RDB file")]
+    pub path: PathBuf,
+    #[structopt(help = "KTID of the entry")]
+    pub ktid: String,
+    #[structopt(
+        long = "set",
+        parse(try_from_str = parse_param),
+        help = "Set the value of a parameter, as param_ktid=value (both in hex)"
+    )]
+    pub set: Vec<(u32, u64)>,
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Where to write the edited RDB (defaults to the input file)"
+    )]
+    pub out_path: Option<PathBuf>,
+}
+
+fn parse_hex(s: &str) -> Result<u64, String> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| format!("{}: {}", s, e))
+}
+
+fn parse_param(s: &str) -> Result<(u32, u64), String> {
+    let (param, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected param_ktid=value, got {}", s))?;
+    Ok((parse_hex(param)? as u32, parse_hex(value)?))
+}
+
+#[derive(Debug, StructOpt)]
+enum Idrk {
+    /// Print the IDRK header of a wrapped file
+    Info {
+        #[structopt(parse(from_os_str), help = "Path to the wrapped file")]
+        path: PathBuf,
+    },
+    /// Strip the IDRK header and write the raw, decompressed asset
+    Unwrap {
+        #[structopt(parse(from_os_str), help = "Path to the wrapped file")]
+        path: PathBuf,
+        #[structopt(parse(from_os_str), help = "Output path of the raw asset")]
+        out_path: PathBuf,
+    },
+    /// Wrap a raw asset in the IDRK header of a RDB entry
+    Wrap {
+        #[structopt(parse(from_os_str), help = "Path to the raw asset")]
+        path: PathBuf,
+        #[structopt(parse(from_os_str), help = "Path to the RDB containing the target entry")]
+        rdb_path: PathBuf,
+        #[structopt(
+            parse(from_os_str),
+            help = "Output path of the wrapped file (defaults to 0x{ktid}.file next to the asset)"
+        )]
+        out_path: Option<PathBuf>,
+        #[structopt(
+            long,
+            help = "KTID (0x...) or file name (name.ext) of the target entry (defaults to the asset file name)"
+        )]
+        ktid: Option<String>,
+        #[structopt(
+            long,
+            default_value = "none",
+            help = "Compression of the wrapped file (none, zlib or lz4)"
+        )]
+        compression: Compression,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct Add {
+    #[structopt(parse(from_os_str), help = "Path to the RDB file")]
+    pub path: PathBuf,
+    #[structopt(parse(from_os_str), help = "Output path to the RDB file")]
+    pub out_path: PathBuf,
+    #[structopt(parse(from_os_str), required = true, help = "Files to add to the RDB")]
+    pub files: Vec<PathBuf>,
+    #[structopt(
+        long,
+        help = "KTID of an existing entry to copy the entry type, TypeInfo and parameters from"
+    )]
+    pub like: Option<String>,
+    #[structopt(
+        long,
+        help = "TypeInfo of the new entries, as a name (TypeInfo::Object::...) or a 0x KTID"
+    )]
+    pub typeinfo: Option<String>,
+    #[structopt(long, default_value = "0", help = "Entry type of the new entries")]
+    pub entry_type: u32,
+    #[structopt(
+        long,
+        default_value = "none",
+        help = "Compression of the generated files (none, zlib or lz4)"
+    )]
+    pub compression: Compression,
+}
+
+#[derive(Debug, StructOpt)]
+struct Remove {
+    #[structopt(parse(from_os_str), help = "Path to the RDB file")]
+    pub path: PathBuf,
+    #[structopt(parse(from_os_str), help = "Output path to the RDB file")]
+    pub out_path: PathBuf,
+    #[structopt(long, help = "KTID (0x...) or file name (name.ext) of an entry to remove")]
+    pub ktid: Vec<String>,
+    #[structopt(
+        long,
+        help = "Remove every entry whose file name (stem.ext) or full name matches, * and ? are supported"
+    )]
+    pub name: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct Rollback {
+    #[structopt(
+        parse(from_os_str),
+        help = "Path to directory containing all mods (defaults to the current directory)"
+    )]
+    pub job_path: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct Diff {
+    #[structopt(parse(from_os_str), help = "Path to the original RDB file")]
+    pub old_path: PathBuf,
+    #[structopt(parse(from_os_str), help = "Path to the modified RDB file")]
+    pub new_path: PathBuf,
+    #[structopt(long, help = "Print the differences as JSON")]
+    pub json: bool,
+}
+
+/// External files are expected in the data directory next to the RDB
+fn out_data_dir(out_path: &Path) -> Result<PathBuf> {
+    let out_data_path = out_path
+        .parent()
+        .map(|p| p.join("data"))
+        .unwrap_or_else(|| PathBuf::from("data"));
+    std::fs::create_dir_all(&out_data_path).with_path(&out_data_path)?;
+    Ok(out_data_path)
+}
+
+/// Name used to find the KTID a file targets
+fn ktid_name(path: &Path) -> Result<&str> {
+    let invalid = || Error::Parse {
+        path: Some(path.to_path_buf()),
+        message: "invalid file name".to_string(),
+    };
+    let file_name = path.file_name().and_then(|x| x.to_str()).ok_or_else(invalid)?;
+    // Check if we're dealing with a KTID or an actual filename
+    if file_name.to_lowercase().starts_with("0x") {
+        // Strip the extension (Cethleann keeps the extension even if the hash is missing)
+        path.file_stem().and_then(|x| x.to_str()).ok_or_else(invalid)
+    } else {
+        // Get the full filename with extension
+        Ok(file_name)
+    }
+}
+
+fn patch_rdb(args: &Patch, options: &ConfigOptions) -> Result<()> {
+    let config = Arc::new(AocConfig::AocConfig::safe_new(options)?);
+    let mut rdb = Rdb::open(&args.path)?;
 
     let external_path = if args.data_path.is_relative() {
-        let rdb_dir = if args.path.is_relative() {
-            std::fs::canonicalize(&args.path)
-                .expect("Failed to canonicalize path")
-                .parent()
-                .expect("Failed to get parent directory for RDB file")
-                .to_path_buf()
+        let rdb_path = if args.path.is_relative() {
+            std::fs::canonicalize(&args.path).with_path(&args.path)?
         } else {
-            args.path
-                .parent()
-                .expect("Unable to get parent dir for RDB file")
-                .to_path_buf()
+            args.path.to_path_buf()
         };
 
-        rdb_dir.join(&args.data_path)
+        rdb_path.parent().unwrap_or_else(|| Path::new("")).join(&args.data_path)
     } else {
         args.data_path.to_path_buf()
     };
 
     let external_path = normalize_path(external_path);
-    println!("{}: External path: {}", line!(), external_path.display());
 
     if !external_path.exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!(
-                "Couldn't find a directory to patch ('{}' was used). Consider making it?",
-                external_path.display()
-            ),
-        ));
-    }
-
-    let files = match std::fs::read_dir(external_path) {
-        Ok(files) => files,
-        Err(e) => {
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Couldn't read the directory: {}", e),
-            ));
-        }
-    };
+        return Err(Error::invalid(format!(
+            "Couldn't find a directory to patch ('{}' was used). Consider making it?",
+            external_path.display()
+        )));
+    }
+
+    let files = std::fs::read_dir(&external_path).with_path(&external_path)?;
+
+    let out_data_path = out_data_dir(&args.out_path)?;
 
     for entry in files {
-        let entry = entry?;
-        println!("{}: entry {:?}", line!(), entry);
-        let metadata = entry.metadata()?;
+        let entry = entry.with_path(&external_path)?;
+        let metadata = entry.metadata().with_path(entry.path())?;
 
         // We don't care about subdirectories
         if metadata.is_dir() {
@@ -116,130 +332,426 @@ fn patch_rdb(args: &Patch) -> io::Result<()> {
 
         let path = &entry.path();
 
-        // Check if we're dealing with a KTID or an actual filename
-        let filename = if path
-            .file_name()
-            .and_then(|x| x.to_str())
-            .expect(&format!("Invalid file_name: {}", path.display()))
-            .to_lowercase()
-            .starts_with("0x")
-        {
-            // Strip the extension (Cethleann keeps the extension even if the hash is missing)
-            path.file_stem()
-                .and_then(|x| x.to_str())
-                .expect(&format!("Invalid file_stem: {}", path.display()))
-        } else {
-            // Get the full filename with extension
-            path.file_name()
-                .and_then(|x| x.to_str())
-                .expect(&format!("Invalid file_name: {}", path.display()))
+        let (filename, file_ktid) = match ktid_name(path).and_then(|name| Ok((name, ktid(name)?))) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("WARNING: {}. Skipping.", e);
+                continue;
+            }
         };
-        println!("{}: filename {:?}", line!(), &filename);
-        println!("{}: entry.path() {:?}", line!(), &entry.path());
 
-        match rdb.get_entry_by_ktid_mut(crate::ktid(filename)) {
+        match rdb.get_entry_by_ktid_mut(file_ktid) {
+            Some(entry_found) if entry_found.is_encrypted() => {
+                eprintln!(
+                    "WARNING: {} targets an encrypted entry, which cannot be patched. Skipping.",
+                    filename
+                );
+            }
             Some(entry_found) => {
                 println!("Patching {}", filename);
-                entry_found.make_external();
-                entry_found.make_uncompressed();
-                let aoc_hash = AocHash::new(&entry.path(), config.clone());
-                if let Ok(_) = entry_found.set_external_file(&aoc_hash) {
-                    println!("{}: set_external_file success", line!());
-                } else {
-                    println!("{}: set_external_file failed", line!());
-                }
-                // entry_found
-                //     .set_external_file(&entry.path())
-                //     .expect("Failed to set external file");
+                let aoc_hash = AocHash::new(entry.path(), config.clone());
+                let destpath = out_data_path.join(entry_found.get_external_path());
+                let rawdata = entry_found.set_external_file(&aoc_hash, args.compression)?;
+                std::fs::write(&destpath, rawdata).with_path(&destpath)?;
             }
             None => println!("File {} not found in the RDB. Skipping.", filename),
         }
     }
 
-    let mut bytes = vec![];
-    rdb.write(&mut bytes)?;
+    rdb.save(&args.out_path)
+}
 
-    std::fs::write(&args.out_path, bytes)
+fn print_entry(args: &Print) -> Result<()> {
+    let ktid = ktid_arg(&args.ktid)?;
+    let rdb = Rdb::open(&args.path)?;
+    if let Some(entry) = rdb.get_entry_by_ktid(&ktid) {
+        println!("{:#?}", entry);
+        match entry.storage() {
+            Some(storage) => println!("Storage: {}, {}", storage, entry.encoding()),
+            None => println!("Storage: none, {}", entry.encoding()),
+        }
+        print!("Params: {}", entry.params());
+    } else {
+        println!("KTID {:?} not found in the RDB.", &ktid);
+    }
+    Ok(())
 }
 
-fn main() -> io::Result<()> {
-    let matches = clap::Command::new("AOC mods merger")
-        .version("1.0")
-        .about("Merging mods for Age of Calamity")
-        .arg(
-            clap::Arg::new("job_path")
-                .help("Path to directory containing all mods")
-                .required(false)
-                .index(1),
-        )
-        .arg(
-            clap::Arg::new("output")
-                .short('o')
-                .long("output")
-                .help("Optional argument where to copy the merged mod directory")
-                .required(false)
-        )
-        .get_matches();
-
-    // Optional job path
-    let tmp = "".to_string();
-    let cwd_dir = matches.get_one::<String>("job_path").unwrap_or_else(|| &tmp).to_string();
-    let output_dir = matches.get_one::<String>("output").unwrap_or_else(|| &tmp).to_string();
-    // let argv = env::args().nth(1).unwrap_or_default(); 
-    let working_dir = if !cwd_dir.is_empty() {
-        PathBuf::from(cwd_dir)
+fn extract_rdb(args: &Extract) -> Result<()> {
+    let container = RdbContainer::open(&args.path)?;
+    let out_dir = args.out_path.join(&container.stem);
+    std::fs::create_dir_all(&out_dir).with_path(&out_dir)?;
+
+    let mut extracted = 0;
+    let mut written = HashSet::new();
+    for entry in &container.rdb.entries {
+        let idrk = match container.read_idrk(entry) {
+            Ok(idrk) => idrk,
+            Err(e) => {
+                println!("Skipping 0x{:08x}: {}", entry.file_ktid, e);
+                continue;
+            }
+        };
+
+        // The IDRK header is what describes the payload, the entry flags may be stale
+        let data = match idrk.unwrap_data() {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Skipping 0x{:08x}: {}", entry.file_ktid, e);
+                continue;
+            }
+        };
+
+        // Fall back to the KTID when two entries share the same resource name
+        let mut file_name = entry.get_resource_file_name();
+        if !written.insert(file_name.clone()) {
+            file_name = entry.get_external_path();
+            written.insert(file_name.clone());
+        }
+        let out_path = out_dir.join(file_name);
+        std::fs::write(&out_path, data).with_path(&out_path)?;
+        extracted += 1;
+    }
+
+    println!(
+        "Extracted {}/{} files to {}",
+        extracted,
+        container.rdb.entries.len(),
+        out_dir.display()
+    );
+    Ok(())
+}
+
+fn edit_params(args: &Params) -> Result<()> {
+    let ktid = ktid(&args.ktid)?;
+    let mut rdb = Rdb::open(&args.path)?;
+    let entry = rdb.get_entry_by_ktid_mut(ktid).ok_or_else(|| Error::UnknownKtid {
+        ktid: ktid.as_u32(),
+        rdb: Some(args.path.clone()),
+    })?;
+
+    let mut params = entry.params();
+    if args.set.is_empty() {
+        print!("{}", params);
+        return Ok(());
+    }
+
+    for (param_ktid, value) in &args.set {
+        match params.get_mut(*param_ktid) {
+            Some(param) => param.value = *value,
+            None => {
+                return Err(Error::invalid(format!(
+                    "Parameter 0x{:08x} not found in entry {}",
+                    param_ktid, &args.ktid
+                )))
+            }
+        }
+    }
+    entry.set_params(&params);
+    print!("{}", params);
+
+    rdb.save(args.out_path.as_ref().unwrap_or(&args.path))
+}
+
+fn add_entries(args: &Add) -> Result<()> {
+    let mut rdb = Rdb::open(&args.path)?;
+    let template = match &args.like {
+        Some(like) => {
+            let like = ktid(like)?;
+            Some(rdb.get_entry_by_ktid(&like).cloned().ok_or_else(|| Error::UnknownKtid {
+                ktid: like.as_u32(),
+                rdb: Some(args.path.clone()),
+            })?)
+        }
+        None => None,
+    };
+    let type_info_ktid = match (&args.typeinfo, &template) {
+        (Some(typeinfo), _) => ktid(typeinfo)?.as_u32(),
+        (None, Some(template)) => template.type_info_ktid,
+        (None, None) => return Err(Error::invalid("New entries need either --typeinfo or --like")),
+    };
+
+    // Nothing is written before every entry has been accepted, so a duplicate KTID can't
+    // overwrite the data of an existing entry
+    let out_data_path = out_data_dir(&args.out_path)?;
+    let mut outputs = Vec::new();
+    for path in &args.files {
+        let raw = std::fs::read(path).with_path(path)?;
+        let mut entry = match &template {
+            Some(template) => RdbEntry::new_external_like(path, template, raw.len() as u64)?,
+            None => RdbEntry::new_external(
+                path,
+                EntryType::from(args.entry_type),
+                type_info_ktid,
+                raw.len() as u64,
+            )?,
+        };
+        entry.type_info_ktid = type_info_ktid;
+
+        let idrk = IdrkEntry::wrap(&entry, &raw, args.compression)?;
+        args.compression.apply_to_flags(&mut entry.flags);
+        let out_path = out_data_path.join(entry.get_external_path());
+
+        println!("Adding {} as 0x{:08x}", path.display(), entry.file_ktid);
+        rdb.add_entry(entry)?;
+        outputs.push((out_path, idrk.to_bytes()?));
+    }
+
+    for (out_path, data) in outputs {
+        std::fs::write(&out_path, data).with_path(&out_path)?;
+    }
+    rdb.save(&args.out_path)
+}
+
+fn remove_entries(args: &Remove) -> Result<()> {
+    if args.ktid.is_empty() && args.name.is_empty() {
+        return Err(Error::invalid("Nothing to remove, use --ktid or --name"));
+    }
+
+    let mut rdb = Rdb::open(&args.path)?;
+    let ktids: Vec<u32> = args
+        .ktid
+        .iter()
+        .map(|x| ktid_arg(x).map(|x| x.as_u32()))
+        .collect::<Result<_>>()?;
+    let removed = rdb.remove_entries(|entry| {
+        let file_name = entry.get_resource_file_name();
+        let full_name = String::from_utf8_lossy(&entry.name);
+        ktids.contains(&entry.file_ktid)
+            || args.name.iter().any(|pattern| {
+                utils::wildcard_match(pattern, &file_name.to_string_lossy())
+                    || utils::wildcard_match(pattern, &full_name)
+            })
+    });
+
+    for ktid in &ktids {
+        if !removed.iter().any(|x| x.file_ktid == *ktid) {
+            println!("KTID 0x{:08x} not found in the RDB.", ktid);
+        }
+    }
+
+    for entry in &removed {
+        println!(
+            "Removed 0x{:08x} ({})",
+            entry.file_ktid,
+            entry.get_resource_file_name().display()
+        );
+        for referencing in rdb.get_entries_referencing(entry.file_ktid) {
+            eprintln!(
+                "WARNING: 0x{:08x} ({}) still references 0x{:08x}",
+                referencing.file_ktid,
+                referencing.get_resource_file_name().display(),
+                entry.file_ktid
+            );
+        }
+    }
+    println!("Removed {} entries, {} left", removed.len(), rdb.entries.len());
+
+    rdb.save(&args.out_path)
+}
+
+fn diff_command(args: &Diff) -> Result<()> {
+    let old = Rdb::open(&args.old_path)?;
+    let new = Rdb::open(&args.new_path)?;
+    let diff = diff::diff_rdbs(&old, &new);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
     } else {
-        env::current_dir()?
+        println!("{}", diff);
+    }
+    Ok(())
+}
+
+fn idrk_command(args: &Idrk) -> Result<()> {
+    match args {
+        Idrk::Info { path } => {
+            let idrk = IdrkEntry::open(path)?;
+            println!("Entry type: {}", idrk.entry_type);
+            println!("File KTID: 0x{:08x}", idrk.file_ktid);
+            println!("TypeInfo KTID: 0x{:08x}", idrk.type_info_ktid);
+            println!("Encoding: {}", idrk.encoding());
+            println!("Header size: 0x{:x}", idrk.header_size());
+            println!("Payload size: 0x{:x}", idrk.string_size);
+            println!("Decompressed size: 0x{:x}", idrk.file_size);
+            print!("Params: {}", idrk.params());
+            Ok(())
+        }
+        Idrk::Unwrap { path, out_path } => {
+            let idrk = IdrkEntry::open(path)?;
+            std::fs::write(out_path, idrk.unwrap_data()?).with_path(out_path)
+        }
+        Idrk::Wrap {
+            path,
+            rdb_path,
+            out_path,
+            ktid: target,
+            compression,
+        } => {
+            let raw = std::fs::read(path).with_path(path)?;
+            if IdrkEntry::is_idrk(&raw) {
+                return Err(Error::invalid(format!("{} is already wrapped", path.display())));
+            }
+
+            let name = match target {
+                Some(target) => target.as_str(),
+                None => ktid_name(path)?,
+            };
+            let file_ktid = ktid_arg(name)?;
+            let rdb = Rdb::open(rdb_path)?;
+            let entry = rdb.get_entry_by_ktid(&file_ktid).ok_or_else(|| Error::UnknownKtid {
+                ktid: file_ktid.as_u32(),
+                rdb: Some(rdb_path.clone()),
+            })?;
+
+            let out_path = out_path.clone().unwrap_or_else(|| {
+                path.parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(entry.get_external_path())
+            });
+            let idrk = IdrkEntry::wrap(entry, &raw, *compression)?;
+            std::fs::write(&out_path, idrk.to_bytes()?).with_path(&out_path)?;
+            println!("Wrote {}", out_path.display());
+            Ok(())
+        }
+    }
+}
+
+fn verify_rdbs(options: &ConfigOptions) -> Result<()> {
+    let roots = AocConfig::AocConfig::safe_new(options)?.romfs_roots();
+
+    let mut checked = 0;
+    let mut failed = 0;
+    // Shadowed RDBs are checked too, every layer has to round-trip
+    for asset_dir in roots.iter().map(|x| x.join("asset")).filter(|x| x.is_dir()) {
+        if roots.len() > 1 {
+            println!("== {}", asset_dir.display());
+        }
+        for entry in walkdir::WalkDir::new(&asset_dir).sort_by_file_name() {
+            let entry = entry.map_err(io::Error::from).with_path(&asset_dir)?;
+            let path = entry.path();
+            let is_rdb = path
+                .extension()
+                .map(|ext| ext.eq_ignore_ascii_case("rdb"))
+                .unwrap_or_default();
+            if !entry.file_type().is_file() || !is_rdb {
+                continue;
+            }
+
+            checked += 1;
+            let name = path.strip_prefix(&asset_dir).unwrap_or(path).display();
+            match Rdb::verify_round_trip(path) {
+                Ok(result) if result.is_exact() => println!("OK       {}", name),
+                Ok(result) => {
+                    failed += 1;
+                    println!(
+                        "MISMATCH {}: first difference at 0x{:x} (original 0x{:x} bytes, written 0x{:x} bytes)",
+                        name,
+                        result.first_difference.unwrap_or_default(),
+                        result.original_len,
+                        result.written_len
+                    );
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!("ERROR    {}: {}", name, e);
+                }
+            }
+        }
+    }
+
+    println!("\n{} RDB files checked, {} failed", checked, failed);
+    if failed > 0 {
+        return Err(Error::invalid(format!("{} RDB files do not round-trip", failed)));
+    }
+    Ok(())
+}
+
+fn rollback_merge(args: &Rollback) -> Result<()> {
+    let working_dir = match &args.job_path {
+        Some(job_path) => job_path.clone(),
+        None => env::current_dir()?,
+    };
+    rollback::rollback(&working_dir.join(ModMerger::MERGED_MOD_NAME))?;
+    println!("Rolled back the last merge");
+    Ok(())
+}
+
+fn merge_mods(args: &Merge, options: &ConfigOptions) -> Result<()> {
+    let working_dir = match &args.job_path {
+        Some(job_path) => job_path.clone(),
+        None => env::current_dir()?,
     };
     let emulator_name = utils::is_emulator_dir(&working_dir);
     if !emulator_name.is_empty() {
         println!("It seems You are trying to work directly inside {} emulator mod directory.", emulator_name);
         println!("Please change the working directory in order to avoid permanent damage to save game files.");
         println!("Press any key to exit...");
-        if let Ok(_) = io::stdin().read(&mut [0u8]) {
+        if io::stdin().read(&mut [0u8]).is_ok() {
             return Ok(());
-        } 
+        }
         std::process::exit(1);
-    } 
-
-
-    // let p = r"C:\Users\Mati\AppData\Roaming\yuzu\load\01002B00111A2000";
-    let mut modmerger = ModMerger::ModMerger::new::<PathBuf>(Some(working_dir))?;
-    if let Err(e) = modmerger.process_mods() {
-        println!("Error: {}", e);
+    }
 
-    } else {
-        println!("Done processing mods");
-        if !output_dir.is_empty() {
-            copy_dirs(&modmerger.root_dir.path, &PathBuf::from(output_dir))?;
+    // A dry run doesn't write the config or the hash cache, and never opens a dialog
+    let options = ConfigOptions {
+        dry_run: args.dry_run,
+        gui: options.gui && !args.dry_run,
+        ..options.clone()
+    };
+    let mut modmerger = ModMerger::ModMerger::new::<PathBuf>(Some(working_dir), &options)?;
+    modmerger.compression = args.compression;
+    modmerger.fail_on_conflict = args.fail_on_conflict;
+    modmerger.dry_run = args.dry_run;
+    modmerger.process_mods()?;
+    println!("Done processing mods");
+    if !args.dry_run {
+        println!(
+            "The mods were left untouched, load only {} in the emulator",
+            modmerger.root_dir.path.display()
+        );
+    }
+    if let Some(output_dir) = &args.output {
+        if args.dry_run {
+            println!(
+                "[dry-run] would copy {} -> {}",
+                modmerger.root_dir.path.display(),
+                output_dir.display()
+            );
+        } else {
+            copy_dirs(&modmerger.root_dir.path, output_dir)?;
         }
     }
 
+    Ok(())
+}
 
-    // return Ok(());
-    // AocConfig::AocConfig::safe_new();
-    // match Opt::from_args_safe() {
-    //     Ok(opt) => match opt.cmd {
-    //         Command::Patch(args) => {
-    //             if let Err(error_msg) = patch_rdb(&args) {
-    //                 println!("{}", error_msg);
-    //             }
-    //         }
-    //         Command::Print(args) => {
-    //             let ktid = ktid(&args.ktid);
-    //             let rdb = Rdb::read(&mut Cursor::new(&std::fs::read(&args.path)?))
-    //                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    //             if let Some(entry) = rdb.get_entry_by_ktid(&ktid) {
-    //                 println!("{:#?}", entry);
-    //             } else {
-    //                 println!("KTID {:?} not found in the RDB.", &ktid);
-    //             }
-    //         }
-    //     },
-    //     Err(e) => {
-    //         println!("{}", e.message);
-    //     }
-    // }
+fn main() -> io::Result<()> {
+    let opt = Opt::from_args();
+    let options = ConfigOptions {
+        romfs: opt.romfs_path,
+        gui: cfg!(feature = "gui") && !opt.no_gui,
+        dry_run: false,
+    };
+
+    let result = match opt.cmd.unwrap_or(Command::Merge(opt.merge)) {
+        Command::Merge(args) => merge_mods(&args, &options),
+        Command::Patch(args) => patch_rdb(&args, &options),
+        Command::Print(args) => print_entry(&args),
+        Command::Extract(args) => extract_rdb(&args),
+        Command::Verify => verify_rdbs(&options),
+        Command::Params(args) => edit_params(&args),
+        Command::Idrk(args) => idrk_command(&args),
+        Command::Add(args) => add_entries(&args),
+        Command::Remove(args) => remove_entries(&args),
+        Command::Diff(args) => diff_command(&args),
+        Command::Rollback(args) => rollback_merge(&args),
+    };
+
+    if let Err(error_msg) = result {
+        println!("Error: {}", error_msg);
+        std::process::exit(1);
+    }
 
     Ok(())
 }
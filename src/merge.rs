@@ -0,0 +1,242 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    diff::diff_entries,
+    rdb::{Rdb, RdbEntry},
+};
+
+/// What a mod did to an entry of the vanilla RDB
+#[derive(Debug, Clone)]
+pub enum EntryChange {
+    Added(RdbEntry),
+    Modified(RdbEntry),
+    Removed,
+}
+
+impl EntryChange {
+    fn entry(&self) -> Option<&RdbEntry> {
+        match self {
+            EntryChange::Added(entry) | EntryChange::Modified(entry) => Some(entry),
+            EntryChange::Removed => None,
+        }
+    }
+
+    /// Two mods doing the exact same change don't conflict
+    fn same_as(&self, other: &EntryChange) -> bool {
+        match (self.entry(), other.entry()) {
+            (Some(a), Some(b)) => diff_entries(a, b).is_empty(),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Entry edited differently in the RDBs shipped by several mods
+    Entry,
+    /// Replacement `.file` shipped by several mods
+    File,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictKind::Entry => write!(f, "entry"),
+            ConflictKind::File => write!(f, "file"),
+        }
+    }
+}
+
+/// A KTID touched by several mods
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub rdb_name: String,
+    pub ktid: u32,
+    /// Resource file name, when known
+    pub name: String,
+    /// Every mod touching the KTID, lowest priority first
+    pub mods: Vec<String>,
+    pub winner: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "0x{:08x} {} in {} ({})", self.ktid, self.name, self.rdb_name, self.kind)?;
+        writeln!(f, "    provided by: {}", self.mods.join(", "))?;
+        write!(f, "    winner: {}", self.winner)
+    }
+}
+
+/// Vanilla RDB with the changes of every mod applied
+pub struct RdbMerge {
+    pub rdb: Rdb,
+    /// Number of entries added, modified or removed
+    pub changed: usize,
+    pub conflicts: Vec<Conflict>,
+    /// Vanilla entries missing from a mod's RDB that the mod doesn't list in `removes`, kept, as `(mod, ktid)`
+    pub kept: Vec<(String, u32)>,
+}
+
+/// Entry changes of `modded` compared to `base`, in entry order
+pub fn entry_changes(base: &Rdb, modded: &Rdb) -> Vec<(u32, EntryChange)> {
+    let base_entries: HashMap<u32, &RdbEntry> =
+        base.entries.iter().map(|x| (x.file_ktid, x)).collect();
+    let modded_entries: HashMap<u32, &RdbEntry> =
+        modded.entries.iter().map(|x| (x.file_ktid, x)).collect();
+
+    let mut changes = Vec::new();
+    for entry in &base.entries {
+        match modded_entries.get(&entry.file_ktid) {
+            Some(modded_entry) if !diff_entries(entry, modded_entry).is_empty() => changes.push((
+                entry.file_ktid,
+                EntryChange::Modified((*modded_entry).clone()),
+            )),
+            Some(_) => (),
+            None => changes.push((entry.file_ktid, EntryChange::Removed)),
+        }
+    }
+    for entry in &modded.entries {
+        if !base_entries.contains_key(&entry.file_ktid) {
+            changes.push((entry.file_ktid, EntryChange::Added(entry.clone())));
+        }
+    }
+    changes
+}
+
+/// Three-way merge of the RDBs shipped by mods against the vanilla one.
+///
+/// `mods` is sorted by priority, lowest first. Changes to different entries are all kept,
+/// entries changed in different ways by several mods are conflicts won by the highest priority mod,
+/// unless `overrides` pins the mod that must win a KTID.
+/// A mod only removes the vanilla entries it lists in `removes`, the other ones missing from its RDB are kept.
+pub fn merge_rdbs(
+    rdb_name: &str,
+    base: Rdb,
+    mods: &[(String, Rdb)],
+    overrides: &HashMap<u32, String>,
+    removes: &HashMap<String, HashSet<u32>>,
+) -> RdbMerge {
+    let mut order: Vec<u32> = Vec::new();
+    let mut changes: HashMap<u32, Vec<(&str, EntryChange)>> = HashMap::new();
+    let mut kept = Vec::new();
+    for (mod_name, rdb) in mods {
+        for (ktid, change) in entry_changes(&base, rdb) {
+            if matches!(change, EntryChange::Removed)
+                && !removes.get(mod_name).is_some_and(|x| x.contains(&ktid))
+            {
+                kept.push((mod_name.clone(), ktid));
+                continue;
+            }
+            if !changes.contains_key(&ktid) {
+                order.push(ktid);
+            }
+            changes.entry(ktid).or_default().push((mod_name, change));
+        }
+    }
+
+    let mut merge = RdbMerge {
+        rdb: base,
+        changed: 0,
+        conflicts: Vec::new(),
+        kept,
+    };
+    for ktid in order {
+        let mod_changes = &changes[&ktid];
+        let (winner, change) = overrides
+            .get(&ktid)
+            .and_then(|pinned| mod_changes.iter().rev().find(|(mod_name, _)| mod_name == pinned))
+            .unwrap_or_else(|| mod_changes.last().unwrap());
+
+        if mod_changes.iter().any(|(_, x)| !x.same_as(change)) {
+            let name = change
+                .entry()
+                .or_else(|| merge.rdb.entries.iter().find(|x| x.file_ktid == ktid))
+                .map(|x| x.get_resource_file_name().display().to_string())
+                .unwrap_or_default();
+            let mut conflict_mods: Vec<String> = Vec::new();
+            for (mod_name, _) in mod_changes {
+                if !conflict_mods.iter().any(|x| x == mod_name) {
+                    conflict_mods.push(mod_name.to_string());
+                }
+            }
+            merge.conflicts.push(Conflict {
+                kind: ConflictKind::Entry,
+                rdb_name: rdb_name.to_string(),
+                ktid,
+                name,
+                mods: conflict_mods,
+                winner: winner.to_string(),
+            });
+        }
+
+        match change {
+            EntryChange::Added(entry) | EntryChange::Modified(entry) => {
+                match merge.rdb.entries.iter_mut().find(|x| x.file_ktid == ktid) {
+                    Some(existing) => *existing = entry.clone(),
+                    None => {
+                        merge.rdb.add_entry(entry.clone()).ok();
+                    }
+                }
+            }
+            EntryChange::Removed => {
+                merge.rdb.remove_entries(|x| x.file_ktid == ktid);
+            }
+        }
+        merge.changed += 1;
+    }
+
+    merge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::tests::sample_rdb;
+
+    fn open_sample() -> Rdb {
+        Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap()
+    }
+
+    #[test]
+    fn merge_two_mods() {
+        let mut mod_a = open_sample();
+        mod_a.entries[0].file_size = 0xa;
+        let mut added = mod_a.entries[1].clone();
+        added.file_ktid = 0x44444444;
+        mod_a.add_entry(added).unwrap();
+
+        let mut mod_b = open_sample();
+        mod_b.entries[0].file_size = 0xb;
+        mod_b.entries[1].type_info_ktid = 0x55555555;
+        mod_b.entries.remove(2);
+
+        let mods = vec![("a".to_string(), mod_a), ("b".to_string(), mod_b)];
+        let removes = HashMap::from([("b".to_string(), HashSet::from([0x33333333]))]);
+        let merge = merge_rdbs("sample.rdb", open_sample(), &mods, &HashMap::new(), &removes);
+
+        assert_eq!(merge.changed, 4);
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].ktid, 0x11111111);
+        assert_eq!(merge.conflicts[0].mods, vec!["a", "b"]);
+        assert_eq!(merge.conflicts[0].winner, "b");
+
+        let ktids: Vec<u32> = merge.rdb.entries.iter().map(|x| x.file_ktid).collect();
+        assert_eq!(ktids, vec![0x11111111, 0x22222222, 0x44444444]);
+        assert_eq!(merge.rdb.header.file_count, 3);
+        assert_eq!(merge.rdb.entries[0].file_size, 0xb);
+        assert_eq!(merge.rdb.entries[1].type_info_ktid, 0x55555555);
+
+        let overrides = HashMap::from([(0x11111111, "a".to_string())]);
+        let merge = merge_rdbs("sample.rdb", open_sample(), &mods, &overrides, &HashMap::new());
+        assert_eq!(merge.conflicts[0].winner, "a");
+        assert_eq!(merge.rdb.entries[0].file_size, 0xa);
+        // Without `removes`, the entry missing from b is kept
+        assert_eq!(merge.kept, vec![("b".to_string(), 0x33333333)]);
+        assert_eq!(merge.rdb.entries.len(), 4);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Context, Result};
+
+/// Name of the state file, at the root of the merged mod
+pub const MERGE_STATE_FILE: &str = "merge_state.json";
+pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// 64-bit FNV-1a, enough to notice that an input changed between two runs
+#[derive(Debug, Clone, Copy)]
+pub struct ContentHash(u64);
+
+impl Default for ContentHash {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl ContentHash {
+    /// Feed a length-prefixed chunk, so consecutive chunks can't be confused
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    let mut hash = ContentHash::default();
+    hash.update(bytes);
+    hash.hex()
+}
+
+/// What the previous merge was made from, to only regenerate what changed.
+///
+/// It is also the manifest of the merged mod: every output file is listed with the mod it comes from.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeState {
+    pub tool_version: String,
+    /// Hash of the options affecting every output (compression, overrides)
+    pub settings: String,
+    /// Merged mods, lowest priority first
+    pub mods: Vec<String>,
+    /// Merged RDBs, by name of the vanilla RDB they are made from
+    pub rdbs: BTreeMap<String, RdbState>,
+    /// Files copied from the mods' add paths (exefs, ...), relative to the merged mod
+    #[serde(default)]
+    pub add_files: BTreeMap<String, OutputFile>,
+    /// Every input file read by the merge, by path
+    #[serde(default)]
+    pub inputs: BTreeMap<String, InputFile>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RdbState {
+    /// Path of the merged RDB, relative to the merged mod
+    pub path: String,
+    /// Hash of the vanilla RDB, the mod RDBs and the data files used for it
+    pub inputs: String,
+    /// Mods with an edited RDB or data files for it
+    pub mods: Vec<String>,
+    /// Every `.file` written to the data directory for this RDB
+    pub files: BTreeMap<String, OutputFile>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputFile {
+    /// Path relative to the merged mod
+    pub path: String,
+    pub hash: String,
+    /// Mod the file comes from
+    pub source: String,
+}
+
+/// Content hash of an input file, reused as long as its size and modification time don't change
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputFile {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch
+    pub modified: u64,
+    pub hash: String,
+}
+
+impl InputFile {
+    /// Hash of the file at `path`, only read if it doesn't match `previous`
+    pub fn new<P: AsRef<Path>>(path: P, previous: Option<&InputFile>) -> Result<Self> {
+        let metadata = fs::metadata(&path).with_path(&path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+            .map(|x| x.as_nanos() as u64)
+            .unwrap_or_default();
+        if let Some(previous) = previous.filter(|x| x.size == metadata.len() && x.modified == modified) {
+            return Ok(previous.clone());
+        }
+        Ok(Self {
+            size: metadata.len(),
+            modified,
+            hash: hash_bytes(&fs::read(&path).with_path(&path)?),
+        })
+    }
+}
+
+impl MergeState {
+    pub fn new(settings: String, mods: Vec<String>) -> Self {
+        Self {
+            tool_version: TOOL_VERSION.to_string(),
+            settings,
+            mods,
+            ..Default::default()
+        }
+    }
+
+    /// Read the state of the previous merge, if there is a usable one
+    pub fn open<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let state: Self = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        (state.tool_version == TOOL_VERSION).then_some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_hash() {
+        assert_eq!(hash_bytes(b"abc"), hash_bytes(b"abc"));
+        assert_ne!(hash_bytes(b"abc"), hash_bytes(b"abd"));
+
+        let mut a = ContentHash::default();
+        a.update(b"ab");
+        a.update(b"c");
+        let mut b = ContentHash::default();
+        b.update(b"a");
+        b.update(b"bc");
+        assert_ne!(a.hex(), b.hex());
+    }
+
+    #[test]
+    fn input_file() {
+        let path = std::env::temp_dir().join(format!("rdb_tool_input_{}", std::process::id()));
+        fs::write(&path, b"abc").unwrap();
+        let input = InputFile::new(&path, None).unwrap();
+        assert_eq!(input.hash, hash_bytes(b"abc"));
+
+        // Same size and time: the previous hash is trusted without reading the file
+        let stale = InputFile {
+            hash: "stale".to_string(),
+            ..input.clone()
+        };
+        assert_eq!(InputFile::new(&path, Some(&stale)).unwrap().hash, "stale");
+        let moved = InputFile {
+            modified: input.modified + 1,
+            ..stale
+        };
+        assert_eq!(InputFile::new(&path, Some(&moved)).unwrap(), input);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
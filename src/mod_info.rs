@@ -0,0 +1,155 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Context, Result},
+    ktid::ktid_arg,
+};
+
+/// Name of the optional metadata file at the root of a mod folder
+pub const MOD_INFO_FILE: &str = "mod.toml";
+
+/// Metadata a mod can describe itself with.
+///
+/// `requires` and `incompatible` list other mods by folder name or by their `name`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    /// Game update the mod was made for
+    pub game_version: Option<String>,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub incompatible: Vec<String>,
+    /// Vanilla entries the mod deletes, as KTIDs (`0x...`) or file names. Entries that are only
+    /// missing from the RDBs it ships are kept.
+    #[serde(default)]
+    pub removes: Vec<String>,
+}
+
+impl ModInfo {
+    /// Read `mod.toml` from a mod folder, if there is one
+    pub fn open<P: AsRef<Path>>(mod_path: P) -> Result<Option<Self>> {
+        let path = mod_path.as_ref().join(MOD_INFO_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        toml::from_str(&fs::read_to_string(&path).with_path(&path)?)
+            .map(Some)
+            .with_path(&path)
+    }
+
+    /// KTIDs listed in `removes`
+    pub fn removed_ktids(&self) -> Result<HashSet<u32>> {
+        self.removes.iter().map(|x| ktid_arg(x).map(|x| x.as_u32())).collect()
+    }
+}
+
+fn matches(folder: &str, info: Option<&ModInfo>, wanted: &str) -> bool {
+    folder.eq_ignore_ascii_case(wanted)
+        || info
+            .and_then(|x| x.name.as_deref())
+            .is_some_and(|name| name.eq_ignore_ascii_case(wanted))
+}
+
+/// Missing requirements and incompatible pairs among the mods being merged, as `(folder, info)`
+pub fn check_dependencies(mods: &[(String, Option<ModInfo>)]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (folder, info) in mods {
+        let Some(info) = info else {
+            continue;
+        };
+        for required in &info.requires {
+            if !mods.iter().any(|(f, i)| matches(f, i.as_ref(), required)) {
+                problems.push(format!("{} requires {}, which is missing or disabled", folder, required));
+            }
+        }
+        for incompatible in &info.incompatible {
+            for (other, _) in mods
+                .iter()
+                .filter(|(f, i)| f != folder && matches(f, i.as_ref(), incompatible))
+            {
+                problems.push(format!("{} is incompatible with {}", folder, other));
+            }
+        }
+    }
+    problems
+}
+
+/// Table of the mods being merged, in priority order
+pub fn summary_table(mods: &[(String, Option<ModInfo>)]) -> String {
+    let header = ["#", "Folder", "Name", "Version", "Author", "Game version"];
+    let mut rows: Vec<[String; 6]> = vec![header.map(str::to_string)];
+    for (i, (folder, info)) in mods.iter().enumerate() {
+        let info = info.clone().unwrap_or_default();
+        let field = |x: Option<String>| x.unwrap_or_else(|| "-".to_string());
+        rows.push([
+            (i + 1).to_string(),
+            folder.to_string(),
+            field(info.name),
+            field(info.version),
+            field(info.author),
+            field(info.game_version),
+        ]);
+    }
+
+    let mut widths = [0; 6];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependencies() {
+        let info: ModInfo = toml::from_str(
+            r#"
+            name = "Better Link"
+            version = "1.0"
+            requires = ["base_textures", "Missing Mod"]
+            incompatible = ["old link"]
+            "#,
+        )
+        .unwrap();
+        let mods = vec![
+            ("base_textures".to_string(), None),
+            ("better_link".to_string(), Some(info)),
+            (
+                "old_link_v2".to_string(),
+                Some(ModInfo {
+                    name: Some("Old Link".to_string()),
+                    ..Default::default()
+                }),
+            ),
+        ];
+
+        assert_eq!(
+            check_dependencies(&mods),
+            vec![
+                "better_link requires Missing Mod, which is missing or disabled",
+                "better_link is incompatible with old_link_v2",
+            ]
+        );
+        assert!(summary_table(&mods).contains("2  better_link    Better Link  1.0"));
+    }
+}
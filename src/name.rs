@@ -0,0 +1,159 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::Utf8Error,
+};
+
+const OPEN_BRACKET: char = '［';
+const CLOSE_BRACKET: char = '］';
+
+/// Name of a RDB entry, usually `R_ext［stem］@size`.
+///
+/// Internal entries carry two more segments with their location in the fdata container:
+/// `R_ext［stem］@size@offset@fdata_id`. All numbers are in hex.
+///
+/// Every part that isn't edited is kept verbatim, so `to_string` gives back the exact original name.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RdbEntryName {
+    /// Type prefix (`R_ext`), or the whole name before the first `@` if there are no brackets
+    pub prefix: String,
+    /// Resource name between the brackets
+    pub resource: Option<String>,
+    /// Anything between the closing bracket and the first `@`
+    pub tail: String,
+    size: Option<String>,
+    /// Segments following the size
+    pub extra: Vec<String>,
+}
+
+impl RdbEntryName {
+    pub fn new(extension: &str, stem: &str, size: u64) -> Self {
+        Self {
+            prefix: format!("R_{}", extension),
+            resource: Some(stem.to_string()),
+            tail: String::new(),
+            size: Some(format!("{:x}", size)),
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn parse(name: &str) -> Self {
+        let mut segments = name.split('@');
+        let base = segments.next().unwrap_or_default();
+        let size = segments.next().map(str::to_string);
+        let extra = segments.map(str::to_string).collect();
+
+        let (prefix, resource, tail) = match base.split_once(OPEN_BRACKET) {
+            Some((prefix, rest)) => match rest.split_once(CLOSE_BRACKET) {
+                Some((resource, tail)) => (prefix, Some(resource.to_string()), tail),
+                None => (base, None, ""),
+            },
+            None => (base, None, ""),
+        };
+
+        Self {
+            prefix: prefix.to_string(),
+            resource,
+            tail: tail.to_string(),
+            size,
+            extra,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Utf8Error> {
+        std::str::from_utf8(bytes).map(Self::parse)
+    }
+
+    /// Extension of the resource, taken from the `R_ext` prefix
+    pub fn extension(&self) -> Option<&str> {
+        self.resource.as_ref()?;
+        self.prefix.strip_prefix("R_").filter(|x| !x.is_empty())
+    }
+
+    /// `stem.ext` when both are known. Names that aren't a single plain file name
+    /// (separators, `..`) are rejected, as they come straight from the RDB.
+    pub fn file_name(&self) -> Option<PathBuf> {
+        match (&self.resource, self.extension()) {
+            (Some(stem), Some(ext)) if !stem.is_empty() => {
+                let name = format!("{}.{}", stem, ext);
+                let mut components = Path::new(&name).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(_)), None) if !name.contains(['/', '\\']) => {
+                        Some(PathBuf::from(name))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+            .as_ref()
+            .and_then(|x| u64::from_str_radix(x, 16).ok())
+    }
+
+    pub fn has_size(&self) -> bool {
+        self.size.is_some()
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        self.size = Some(format!("{:x}", size));
+    }
+
+    /// Hex value of an extra segment
+    pub fn extra_hex(&self, index: usize) -> Option<u64> {
+        self.extra
+            .get(index)
+            .and_then(|x| u64::from_str_radix(x.trim_end_matches('\0'), 16).ok())
+    }
+
+    pub fn rename(&mut self, stem: &str) {
+        self.resource = Some(stem.to_string());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl fmt::Display for RdbEntryName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.prefix)?;
+        if let Some(resource) = &self.resource {
+            write!(f, "{}{}{}", OPEN_BRACKET, resource, CLOSE_BRACKET)?;
+        }
+        write!(f, "{}", self.tail)?;
+        if let Some(size) = &self.size {
+            write!(f, "@{}", size)?;
+        }
+        for segment in &self.extra {
+            write!(f, "@{}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_write_back() {
+        for name in ["R_g1t［foo］@1234", "R_g1m［bar］@10@20@0", "no_brackets@0A", "", "R_［］x"] {
+            assert_eq!(RdbEntryName::parse(name).to_string(), name);
+        }
+
+        let mut name = RdbEntryName::parse("R_g1t［foo_bar］@1234");
+        assert_eq!(name.file_name(), Some(PathBuf::from("foo_bar.g1t")));
+        assert_eq!(name.size(), Some(0x1234));
+        name.set_size(0xabc);
+        assert_eq!(name.to_string(), "R_g1t［foo_bar］@abc");
+        assert_eq!(RdbEntryName::new("g1t", "foo_bar", 0xabc), name);
+
+        for name in ["R_g1t［../foo］@1", "R_g1t［a/b］@1", "R_g1t［a\\b］@1", "R_g1t［/］@1"] {
+            assert_eq!(RdbEntryName::parse(name).file_name(), None, "{}", name);
+        }
+    }
+}
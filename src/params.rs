@@ -0,0 +1,147 @@
+use std::{fmt, io::Cursor};
+
+use binread::{BinRead, BinReaderExt};
+use binwrite::BinWrite;
+
+/// Size of the block header (unk + count)
+const BLOCK_HEADER_SIZE: usize = 8;
+/// Size of a single parameter
+const PARAM_SIZE: usize = 0x10;
+
+/// A KTID-keyed parameter of a RDB entry
+#[derive(BinRead, BinWrite, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RdbParam {
+    pub param_ktid: u32,
+    pub unk: u32,
+    pub value: u64,
+}
+
+impl RdbParam {
+    /// Values are most of the time the KTID of another resource
+    pub fn value_ktid(&self) -> u32 {
+        self.value as u32
+    }
+}
+
+/// Parameter block stored in `RdbEntry::unk_content`
+#[derive(BinRead, BinWrite, Debug, Clone, PartialEq, Eq)]
+#[binwrite(little)]
+pub struct RdbParamBlock {
+    pub unk: u32,
+    pub count: u32,
+    #[br(count = count)]
+    pub params: Vec<RdbParam>,
+}
+
+/// Decoded `unk_content`, with the original bytes kept for anything that doesn't match the expected layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RdbParams {
+    Parsed(RdbParamBlock),
+    Raw(Vec<u8>),
+}
+
+impl RdbParams {
+    pub fn parse(bytes: &[u8]) -> Self {
+        if bytes.len() < BLOCK_HEADER_SIZE
+            || !(bytes.len() - BLOCK_HEADER_SIZE).is_multiple_of(PARAM_SIZE)
+        {
+            return RdbParams::Raw(bytes.to_vec());
+        }
+
+        let count = (bytes.len() - BLOCK_HEADER_SIZE) / PARAM_SIZE;
+        match Cursor::new(bytes).read_le::<RdbParamBlock>() {
+            Ok(block) if block.count as usize == count => RdbParams::Parsed(block),
+            _ => RdbParams::Raw(bytes.to_vec()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RdbParams::Parsed(block) => {
+                let mut block = block.clone();
+                block.count = block.params.len() as u32;
+
+                let mut bytes = Vec::new();
+                block.write(&mut bytes).expect("Writing to a Vec can't fail");
+                bytes
+            }
+            RdbParams::Raw(bytes) => bytes.clone(),
+        }
+    }
+
+    pub fn params(&self) -> &[RdbParam] {
+        match self {
+            RdbParams::Parsed(block) => &block.params,
+            RdbParams::Raw(_) => &[],
+        }
+    }
+
+    pub fn get_mut(&mut self, param_ktid: u32) -> Option<&mut RdbParam> {
+        match self {
+            RdbParams::Parsed(block) => block.params.iter_mut().find(|x| x.param_ktid == param_ktid),
+            RdbParams::Raw(_) => None,
+        }
+    }
+
+    /// KTIDs of the other resources this block points to
+    pub fn dependencies(&self) -> Vec<u32> {
+        self.params()
+            .iter()
+            .map(RdbParam::value_ktid)
+            .filter(|x| *x != 0)
+            .collect()
+    }
+
+    /// Whether a value points to `ktid`. Keys name the parameter, they never point to a resource.
+    pub fn references(&self, ktid: u32) -> bool {
+        self.params().iter().any(|x| x.value_ktid() == ktid)
+    }
+}
+
+impl fmt::Display for RdbParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RdbParams::Parsed(block) => {
+                writeln!(f, "{} params (unk: 0x{:08x})", block.count, block.unk)?;
+                for param in &block.params {
+                    writeln!(
+                        f,
+                        "  0x{:08x} = 0x{:016x} (unk: 0x{:08x})",
+                        param.param_ktid, param.value, param.unk
+                    )?;
+                }
+                Ok(())
+            }
+            RdbParams::Raw(bytes) => {
+                write!(f, "raw ({} bytes):", bytes.len())?;
+                for byte in bytes {
+                    write!(f, " {:02x}", byte)?;
+                }
+                writeln!(f)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_write_back() {
+        let mut bytes = vec![0xaa, 0, 0, 0, 1, 0, 0, 0];
+        bytes.extend_from_slice(&0x11223344u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0xcafebabeu64.to_le_bytes());
+
+        let params = RdbParams::parse(&bytes);
+        assert_eq!(params.params().len(), 1);
+        assert_eq!(params.dependencies(), vec![0xcafebabe]);
+        assert!(params.references(0xcafebabe));
+        assert!(!params.references(0x11223344));
+        assert_eq!(params.to_bytes(), bytes);
+
+        // Sizes that can't hold a block are kept as is
+        assert_eq!(RdbParams::parse(&bytes[..12]), RdbParams::Raw(bytes[..12].to_vec()));
+    }
+}
@@ -1,17 +1,21 @@
-#![allow(dead_code)]
 use std::{
-    fs::OpenOptions,
-    io::{self, Read, Seek, SeekFrom},
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     str::Utf8Error,
 };
 
-use binread::{BinRead, BinReaderExt, BinResult, NullString};
+use binread::{helpers::until_eof, BinRead, BinReaderExt, BinResult, NullString, ReadOptions};
 
-use binwrite::BinWrite;
+use binwrite::{BinWrite, WriterOption};
 
 use modular_bitfield::prelude::*;
 
+use crate::compression::{self, Compression};
+use crate::error::{Context, Error, Result};
+use crate::ktid::KTID;
+use crate::name::RdbEntryName;
+use crate::params::RdbParams;
 use crate::ModMerger::AocHash;
 
 #[derive(BinRead, BinWrite, Debug)]
@@ -24,7 +28,106 @@ pub struct RdbHeader {
     pub file_count: u32,
     pub ktid: u32,
     #[br(map = NullString::into_string)]
+    #[binwrite(cstr)]
     pub path: String,
+    /// Whatever sits between the path and `header_size`, kept so the header is written back as is
+    #[br(count = (header_size as usize).saturating_sub(0x18 + path.len() + 1))]
+    pub padding: Vec<u8>,
+}
+
+pub const IDRK_MAGIC: u32 = u32::from_le_bytes(*b"IDRK");
+
+/// Size of the fields every entry starts with, up to and including the flags
+pub const ENTRY_FIXED_SIZE: usize = 0x30;
+
+/// Size of the parameter block between the fixed fields and the name (or payload),
+/// `None` if `entry_size` is too small to hold them
+fn param_block_size(entry_size: u32, string_size: u32) -> Option<usize> {
+    (entry_size as usize).checked_sub(string_size as usize)?.checked_sub(ENTRY_FIXED_SIZE)
+}
+
+/// Kind of resource an entry describes. It decides how many parameters the entry carries,
+/// and therefore the size of the IDRK header in front of its data.
+///
+/// Only these five types are known so far. Every other value is kept as `Other` and written back as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryType {
+    /// No parameters besides the block header
+    Plain,
+    /// KidsSingletonDb?
+    SingletonDb,
+    /// G1E
+    Effect,
+    /// G1A, G1T
+    Texture,
+    /// G1M, most likely other model related formats
+    Model,
+    /// Anything else (sound banks, scripts...). The header size is taken from the entry itself
+    Other(u32),
+}
+
+impl EntryType {
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            EntryType::Plain => 0,
+            EntryType::SingletonDb => 1,
+            EntryType::Effect => 4,
+            EntryType::Texture => 8,
+            EntryType::Model => 12,
+            EntryType::Other(value) => *value,
+        }
+    }
+
+    /// Expected size of the IDRK header, `None` for types we know nothing about
+    pub fn header_size(&self) -> Option<u32> {
+        match self {
+            EntryType::Plain => Some(0x38),
+            EntryType::SingletonDb | EntryType::Effect => Some(0x48),
+            EntryType::Texture => Some(0x58),
+            EntryType::Model => Some(0x68),
+            EntryType::Other(_) => None,
+        }
+    }
+}
+
+impl From<u32> for EntryType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => EntryType::Plain,
+            1 => EntryType::SingletonDb,
+            4 => EntryType::Effect,
+            8 => EntryType::Texture,
+            12 => EntryType::Model,
+            _ => EntryType::Other(value),
+        }
+    }
+}
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryType::Other(value) => write!(f, "{}", value),
+            _ => write!(f, "{} ({:?})", self.as_u32(), self),
+        }
+    }
+}
+
+impl BinRead for EntryType {
+    type Args = ();
+
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        options: &ReadOptions,
+        args: Self::Args,
+    ) -> BinResult<Self> {
+        u32::read_options(reader, options, args).map(EntryType::from)
+    }
+}
+
+impl BinWrite for EntryType {
+    fn write_options<W: Write>(&self, writer: &mut W, options: &WriterOption) -> io::Result<()> {
+        self.as_u32().write_options(writer, options)
+    }
 }
 
 #[derive(BinRead, BinWrite, Debug, Clone)]
@@ -34,14 +137,20 @@ pub struct RdbEntry {
     #[br(assert(version == 0x30303030))]
     pub entry_size: u32,
     pub unk: u32,
+    #[br(assert(
+        param_block_size(entry_size, string_size).is_some(),
+        "Entry size 0x{:x} is smaller than its name (0x{:x} bytes)",
+        entry_size,
+        string_size
+    ))]
     pub string_size: u32,
     pub unk2: u32,
     pub file_size: u64,
-    pub entry_type: u32,
+    pub entry_type: EntryType,
     pub file_ktid: u32,
     pub type_info_ktid: u32,
     pub flags: RdbFlags,
-    #[br(count = (entry_size - string_size) - 0x30)]
+    #[br(count = param_block_size(entry_size, string_size).unwrap_or_default())]
     pub unk_content: Vec<u8>,
     #[br(count = string_size, align_after = 4)]
     #[binwrite(align_after(4))]
@@ -50,159 +159,301 @@ pub struct RdbEntry {
 
 #[derive(BinRead, BinWrite, Debug, Clone)]
 pub struct IdrkEntry {
+    #[br(assert(magic == IDRK_MAGIC, "Missing IDRK header"))]
     pub magic: u32,
     pub version: u32,
     #[br(assert(version == 0x30303030))]
     pub entry_size: u32,
     pub unk: u32,
+    #[br(assert(
+        param_block_size(entry_size, string_size).is_some(),
+        "IDRK header size 0x{:x} is smaller than its payload (0x{:x} bytes)",
+        entry_size,
+        string_size
+    ))]
     pub string_size: u32,
     pub unk2: u32,
     pub file_size: u64,
-    pub entry_type: u32,
+    pub entry_type: EntryType,
     pub file_ktid: u32,
     pub type_info_ktid: u32,
     pub flags: RdbFlags,
-    #[br(count = (entry_size - string_size) - 0x30)]
+    #[br(count = param_block_size(entry_size, string_size).unwrap_or_default())]
     pub unk_content: Vec<u8>,
-    #[br(count = string_size, align_after = 4)]
-    #[binwrite(align_after(4))]
-    pub name: Vec<u8>,
+    /// IDRK blobs have no name, `string_size` is the size of the payload instead
+    #[br(count = string_size)]
+    pub data: Vec<u8>,
 }
 
-impl RdbEntry {
-    pub fn get_external_path(&self) -> PathBuf {
-        PathBuf::from(&format!("0x{:08x}.file", self.file_ktid))
+impl IdrkEntry {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        std::fs::File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| Self::from_reader(&mut std::io::BufReader::new(file)))
+            .with_path(path)
     }
 
-    pub fn make_external(&mut self) {
-        self.flags.set_external(true);
-        self.flags.set_internal(false);
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        Ok(reader.read_le()?)
     }
 
-    pub fn make_uncompressed(&mut self) {
-        self.flags.set_zlib_compressed(false);
-        self.flags.set_lz4_compressed(false);
+    /// Size of the header in front of the payload: the fixed fields and the parameter block
+    pub fn header_size(&self) -> u32 {
+        (ENTRY_FIXED_SIZE + self.unk_content.len()) as u32
     }
 
-    pub fn get_name(&mut self) -> Result<&str, Utf8Error> {
-        std::str::from_utf8(self.name.as_slice())
+    pub fn is_idrk(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"IDRK")
     }
 
-    pub fn get_name_mut(&mut self) -> Result<&mut str, Utf8Error> {
-        std::str::from_utf8_mut(self.name.as_mut_slice())
+    /// Wrap a raw asset in an IDRK header built from the RDB entry it belongs to
+    pub fn wrap(entry: &RdbEntry, data: &[u8], compression: Compression) -> Result<Self> {
+        let header_size = entry.idrk_header_size()?;
+        let payload = compression::compress(data, compression)?;
+        let mut flags = RdbFlags::new();
+        compression.apply_to_flags(&mut flags);
+
+        // The IDRK header keeps the decompressed size, string_size holds the size of the payload
+        Ok(Self {
+            magic: IDRK_MAGIC,
+            version: entry.version,
+            entry_size: header_size + payload.len() as u32,
+            unk: entry.unk,
+            string_size: payload.len() as _,
+            unk2: entry.unk2,
+            file_size: data.len() as _,
+            entry_type: entry.entry_type,
+            file_ktid: entry.file_ktid,
+            type_info_ktid: entry.type_info_ktid,
+            flags,
+            unk_content: entry.unk_content.clone(),
+            data: payload,
+        })
     }
 
-    pub fn set_external_file(&mut self, path: &AocHash) -> io::Result<Vec<u8>> {
-        let mut name = self.get_name_mut().unwrap_or_default().to_string();
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.write(&mut buffer)?;
+        Ok(buffer)
+    }
 
-        self.file_size = Path::new(&path.path.full_path).metadata()?.len();
+    /// Payload following the header, still compressed if the flags say so
+    pub fn payload(&self) -> &[u8] {
+        &self.data
+    }
 
-        if let Some(size_marker) = name.find('@') {
-            name.replace_range(size_marker.., &format!("@{:x}", self.file_size));
-        }
+    pub fn encoding(&self) -> RdbEncoding {
+        self.flags.encoding()
+    }
 
-        if self.file_size == 0 {
-            println!("Filesize is 0. Are you sure about that?");
+    pub fn params(&self) -> RdbParams {
+        RdbParams::parse(&self.unk_content)
+    }
+
+    /// Decompressed payload, i.e. the raw asset
+    pub fn unwrap_data(&self) -> Result<Vec<u8>> {
+        match self.encoding().compression() {
+            Some(compression) => compression::decompress(&self.data, compression),
+            None => Err(Error::UnsupportedEntryType {
+                ktid: self.file_ktid,
+                message: "encrypted entries can't be unwrapped".to_string(),
+            }),
         }
+    }
+}
+
+impl RdbEntry {
+    /// New external entry for the file at `path`, its KTID is the one of the `R_ext［stem］` name
+    pub fn new_external<P: AsRef<Path>>(
+        path: P,
+        entry_type: EntryType,
+        type_info_ktid: u32,
+        file_size: u64,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let (stem, extension) = match (
+            path.file_stem().and_then(|x| x.to_str()),
+            path.extension().and_then(|x| x.to_str()),
+        ) {
+            (Some(stem), Some(extension)) if !stem.is_empty() && !extension.is_empty() => {
+                (stem, extension)
+            }
+            _ => {
+                return Err(Error::invalid(format!(
+                    "{} needs a name and an extension",
+                    path.display()
+                )))
+            }
+        };
+
+        let name = RdbEntryName::new(extension, stem, file_size).to_bytes();
+        // Known types get a zeroed block of the expected size, others an empty block
+        let unk_content = vec![
+            0;
+            entry_type
+                .header_size()
+                .map(|x| x as usize - ENTRY_FIXED_SIZE)
+                .unwrap_or(8)
+        ];
+
+        let mut flags = RdbFlags::new();
+        flags.set_external(true);
+
+        Ok(Self {
+            magic: IDRK_MAGIC,
+            version: 0x30303030,
+            entry_size: (ENTRY_FIXED_SIZE + unk_content.len() + name.len()) as u32,
+            unk: 0,
+            string_size: name.len() as u32,
+            unk2: 0,
+            file_size,
+            entry_type,
+            file_ktid: KTID::new(path)?.as_u32(),
+            type_info_ktid,
+            flags,
+            unk_content,
+            name,
+        })
+    }
+
+    /// Same as `new_external`, with the type, parameters and unknown fields of an existing entry
+    pub fn new_external_like<P: AsRef<Path>>(
+        path: P,
+        template: &RdbEntry,
+        file_size: u64,
+    ) -> Result<Self> {
+        let mut entry = Self::new_external(
+            path,
+            template.entry_type,
+            template.type_info_ktid,
+            file_size,
+        )?;
+        entry.unk = template.unk;
+        entry.unk2 = template.unk2;
+        entry.set_params(&template.params());
+        Ok(entry)
+    }
+
+    pub fn get_external_path(&self) -> PathBuf {
+        PathBuf::from(&format!("0x{:08x}.file", self.file_ktid))
+    }
+
+    /// File name of the resource, rebuilt from the `R_ext［stem］` entry name when possible
+    pub fn get_resource_file_name(&self) -> PathBuf {
+        self.entry_name()
+            .ok()
+            .and_then(|name| name.file_name())
+            .unwrap_or_else(|| self.get_external_path())
+    }
+
+    pub fn entry_name(&self) -> Result<RdbEntryName, Utf8Error> {
+        RdbEntryName::from_bytes(&self.name)
+    }
 
+    pub fn set_name(&mut self, name: &RdbEntryName) {
         // Remove the size of the original string
         self.entry_size -= self.string_size;
         // Put the edited name back into the entry
-        self.name = name.as_bytes().to_vec();
+        self.name = name.to_bytes();
         // Fix the size of the string
-        self.string_size = name.len() as _;
+        self.string_size = self.name.len() as _;
         // Edit the size of the entry to take the new name into account
         self.entry_size += self.string_size;
+    }
 
-        let mut ext_entry = self.clone();
-        ext_entry.patch_external_file(path)
-        // Ok(())
+    /// Size of the IDRK header written in front of the file: the fixed fields and the parameter block
+    pub fn idrk_header_size(&self) -> Result<u32> {
+        let header_size = (ENTRY_FIXED_SIZE + self.unk_content.len()) as u32;
+        match self.entry_type.header_size() {
+            Some(expected) if expected != header_size => Err(Error::UnsupportedEntryType {
+                ktid: self.file_ktid,
+                message: format!(
+                    "entry type {} expects a 0x{:x} bytes header, this one has 0x{:x}",
+                    self.entry_type, expected, header_size
+                ),
+            }),
+            _ => Ok(header_size),
+        }
     }
 
-    pub fn patch_external_file(&mut self, path: &AocHash) -> io::Result<Vec<u8>> {
-        self.name = vec![];
-        //self.write(&mut bytes).unwrap();
+    pub fn params(&self) -> RdbParams {
+        RdbParams::parse(&self.unk_content)
+    }
 
-        //let cursor : &mut std::io::Cursor<Vec<u8>> = &mut std::io::Cursor::new(Vec::new());
-        let mut test = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&path.path.full_path)?;
+    pub fn set_params(&mut self, params: &RdbParams) {
+        let bytes = params.to_bytes();
+        self.entry_size = self.entry_size - self.unk_content.len() as u32 + bytes.len() as u32;
+        self.unk_content = bytes;
+    }
 
-        //let file = std::fs::read(path).unwrap();
+    pub fn encoding(&self) -> RdbEncoding {
+        self.flags.encoding()
+    }
 
-        let mut buffer = Vec::new();
+    pub fn storage(&self) -> Option<RdbStorage> {
+        self.flags.storage()
+    }
 
-        let mut out_sig = [0; 4];
-        test.read(&mut out_sig)?;
+    pub fn is_encrypted(&self) -> bool {
+        self.encoding() == RdbEncoding::Encrypted
+    }
 
-        if &out_sig == b"IDRK" {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                "Already patched",
-            ));
-        }
+    pub fn make_external(&mut self) {
+        self.flags.set_external(true);
+        self.flags.set_internal(false);
+    }
 
-        test.seek(SeekFrom::Start(0))?;
-        let header_size = match self.entry_type {
-            0 => 0x38,
-            // 1 is KidsSingletonDb? 4 is G1E
-            1 | 4 => 0x48,
-            // G1A, G1T
-            8 => 0x58,
-            // G1M, most likely other model related formats
-            12 => 0x68,
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Unknown entry type {}", self.entry_type),
-                ))
-            }
+    pub fn make_uncompressed(&mut self) {
+        self.flags.set_zlib_compressed(false);
+        self.flags.set_lz4_compressed(false);
+    }
+
+    pub fn get_name(&mut self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(self.name.as_slice())
+    }
+
+    pub fn get_name_mut(&mut self) -> Result<&mut str, Utf8Error> {
+        std::str::from_utf8_mut(self.name.as_mut_slice())
+    }
+
+    /// Make the entry external and return the wrapped file to write to the data directory.
+    /// Files that are already wrapped are returned as is, the entry taking the encoding of their header.
+    pub fn set_external_file(
+        &mut self,
+        path: &AocHash,
+        compression: Compression,
+    ) -> Result<Vec<u8>> {
+        let full_path = &path.path.full_path;
+        let raw = std::fs::read(full_path).with_path(full_path)?;
+
+        let (buffer, flags) = if IdrkEntry::is_idrk(&raw) {
+            let idrk = IdrkEntry::from_reader(&mut io::Cursor::new(&raw)).with_path(full_path)?;
+            self.file_size = idrk.file_size;
+            (raw, idrk.flags)
+        } else {
+            self.file_size = raw.len() as u64;
+            let mut flags = RdbFlags::new();
+            compression.apply_to_flags(&mut flags);
+            (IdrkEntry::wrap(self, &raw, compression)?.to_bytes()?, flags)
         };
-        self.entry_size = header_size + test.metadata()?.len() as u32;
-        self.file_size = test.metadata()?.len() as _;
-        self.string_size = self.file_size as _;
-        self.flags = RdbFlags::new();
+        self.make_external();
+        self.flags.set_zlib_compressed(flags.zlib_compressed());
+        self.flags.set_lz4_compressed(flags.lz4_compressed());
+
+        let mut name = self.entry_name().unwrap_or_default();
+        if name.has_size() {
+            name.set_size(self.file_size);
+            // The fdata location is meaningless once the file is external
+            name.extra.clear();
+        }
 
-        //test.seek(SeekFrom::Start(0)).unwrap();
-        self.write(&mut buffer)?;
-        test.read_to_end(&mut buffer)?;
-
-        // let mut writer = BufWriter::new(test);
-        // writer.seek(SeekFrom::Start(0)).unwrap();
-
-        // self.write(&mut writer).unwrap();
-        //writer.write_all(&mut reader.buffer());
-        // Check if we're dealing with a KTID or an actual filename
-        // let filename = if path
-        //     .file_name()
-        //     .unwrap_or_default()
-        //     .to_str()
-        //     .unwrap_or_default()
-        //     .to_lowercase()
-        //     .starts_with("0x")
-        // {
-        //     // Strip the extension (Cethleann keeps the extension even if the hash is missing)
-        //     path.file_stem()
-        //         .and_then(|x| x.to_str())
-        //         .expect("Invalid file_stem")
-        // } else {
-        //     // Get the full filename with extension
-        //     path.file_name()
-        //         .and_then(|x| x.to_str())
-        //         .expect("Invalid file_name")
-        // };
-
-        // let out_path = PathBuf::from(format!("./data/0x{}.file", crate::ktid(filename)));
-        let mut out_path = PathBuf::from(&path.path.parent);
-        out_path.push(format!("0x{}.file", &path.hash));
-
-        // if !out_path.exists() {
-        //     std::fs::create_dir_all("./data/")?;
-        // }
+        if self.file_size == 0 {
+            println!("Filesize is 0. Are you sure about that?");
+        }
+
+        self.set_name(&name);
         Ok(buffer)
-        // std::fs::write(out_path, &buffer)
     }
 }
 
@@ -214,21 +465,23 @@ pub struct Rdb {
     #[br(seek_before = SeekFrom::Start(header.header_size as _), count = header.file_count)]
     #[binwrite(align(4))]
     pub entries: Vec<RdbEntry>,
+    /// Trailing bytes after the last entry, if any
+    #[br(parse_with = until_eof)]
+    pub trailer: Vec<u8>,
 }
 
 impl Rdb {
-    pub fn open<P: AsRef<Path>>(path: P) -> BinResult<Self> {
-        Self::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
-    }
-
-    pub fn open_io<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        Self::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        std::fs::File::open(path)
+            .map_err(Error::from)
+            .and_then(|file| Self::from_reader(std::io::BufReader::new(file)))
+            .with_path(path)
     }
 
     pub fn from_reader<R: std::io::Read + std::io::Seek + Send + 'static>(
         mut reader: R,
-    ) -> BinResult<Self> {
+    ) -> Result<Self> {
         let rdb: Self = reader.read_le()?;
 
         Ok(rdb)
@@ -244,14 +497,91 @@ impl Rdb {
             .find(|x| x.file_ktid == ktid.as_u32())
     }
 
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+    /// Remove every entry matching `predicate`, keeping the header in sync
+    pub fn remove_entries<F: FnMut(&RdbEntry) -> bool>(&mut self, mut predicate: F) -> Vec<RdbEntry> {
+        let (removed, kept) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|x| predicate(x));
+        self.entries = kept;
+        self.header.file_count = self.entries.len() as u32;
+        removed
+    }
+
+    pub fn remove_entry_by_ktid(&mut self, ktid: &KTID) -> Option<RdbEntry> {
+        self.remove_entries(|x| x.file_ktid == ktid.as_u32()).pop()
+    }
+
+    /// Entries whose parameter block references `ktid`
+    pub fn get_entries_referencing(&self, ktid: u32) -> Vec<&RdbEntry> {
+        self.entries
+            .iter()
+            .filter(|x| x.file_ktid != ktid && x.params().references(ktid))
+            .collect()
+    }
+
+    /// Append a new entry, keeping the header in sync
+    pub fn add_entry(&mut self, entry: RdbEntry) -> Result<&mut RdbEntry> {
+        if self.entries.iter().any(|x| x.file_ktid == entry.file_ktid) {
+            return Err(Error::invalid(format!(
+                "KTID 0x{:08x} already exists in the RDB",
+                entry.file_ktid
+            )));
+        }
+
+        self.entries.push(entry);
+        self.header.file_count = self.entries.len() as u32;
+        Ok(self.entries.last_mut().unwrap())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
         let mut bytes = vec![];
         self.write(&mut bytes)?;
-    
-        std::fs::write(&path, bytes)
+        Ok(bytes)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(&path, self.to_bytes()?).with_path(path)
+    }
+
+    /// Parse the RDB at `path`, serialize it again and compare the result with the original bytes
+    pub fn verify_round_trip<P: AsRef<Path>>(path: P) -> Result<RoundTrip> {
+        let original = std::fs::read(&path).with_path(&path)?;
+        let rdb = Self::from_reader(io::Cursor::new(original.clone())).with_path(&path)?;
+
+        let mut written = vec![];
+        rdb.write(&mut written)?;
+
+        Ok(RoundTrip {
+            original_len: original.len(),
+            written_len: written.len(),
+            first_difference: first_difference(&original, &written),
+        })
+    }
+}
+
+/// Result of `Rdb::verify_round_trip`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundTrip {
+    pub original_len: usize,
+    pub written_len: usize,
+    /// Offset of the first byte that differs, `None` if both files are identical
+    pub first_difference: Option<usize>,
+}
+
+impl RoundTrip {
+    pub fn is_exact(&self) -> bool {
+        self.first_difference.is_none()
     }
 }
 
+/// Offset of the first differing byte, or the length of the shortest slice if one is a prefix of the other
+pub fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(x, y)| x != y)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+}
+
 #[bitfield]
 #[derive(BinRead, BinWrite, Debug, Copy, Clone)]
 #[br(map = Self::from_bytes)]
@@ -265,3 +595,266 @@ pub struct RdbFlags {
     pub lz4_compressed: bool,
     pub unk3: B10,
 }
+
+impl Default for RdbFlags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RdbFlags {
+    pub fn encoding(&self) -> RdbEncoding {
+        match (self.zlib_compressed(), self.lz4_compressed()) {
+            (false, false) => RdbEncoding::Uncompressed,
+            (true, false) => RdbEncoding::Zlib,
+            (false, true) => RdbEncoding::Lz4,
+            (true, true) => RdbEncoding::Encrypted,
+        }
+    }
+
+    /// `None` if neither storage bit is set
+    pub fn storage(&self) -> Option<RdbStorage> {
+        if self.external() {
+            Some(RdbStorage::External)
+        } else if self.internal() {
+            Some(RdbStorage::Internal)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the payload of an entry is encoded, according to the compression bits of `RdbFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RdbEncoding {
+    Uncompressed,
+    Zlib,
+    Lz4,
+    /// Both compression bits set. We have no way to decrypt those, so they must be left untouched
+    Encrypted,
+}
+
+impl RdbEncoding {
+    /// `None` for encrypted payloads
+    pub fn compression(&self) -> Option<Compression> {
+        match self {
+            RdbEncoding::Uncompressed => Some(Compression::None),
+            RdbEncoding::Zlib => Some(Compression::Zlib),
+            RdbEncoding::Lz4 => Some(Compression::Lz4),
+            RdbEncoding::Encrypted => None,
+        }
+    }
+}
+
+/// Where the payload of an entry is stored, according to the storage bits of `RdbFlags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RdbStorage {
+    /// Standalone `0x{ktid}.file` in the data directory
+    External,
+    /// Inside a `.fdata` container
+    Internal,
+}
+
+impl fmt::Display for RdbEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RdbEncoding::Uncompressed => write!(f, "uncompressed"),
+            RdbEncoding::Zlib => write!(f, "zlib"),
+            RdbEncoding::Lz4 => write!(f, "lz4"),
+            RdbEncoding::Encrypted => write!(f, "encrypted"),
+        }
+    }
+}
+
+impl fmt::Display for RdbStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RdbStorage::External => write!(f, "external"),
+            RdbStorage::Internal => write!(f, "internal"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn entry_bytes(entry_type: u32, file_ktid: u32, params: &[u8], name: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let fields = [
+            u32::from_le_bytes(*b"IDRK"),
+            0x30303030,
+            (0x30 + params.len() + name.len()) as u32,
+            0,
+            name.len() as u32,
+            0,
+        ];
+        fields.iter().for_each(|x| bytes.extend_from_slice(&x.to_le_bytes()));
+        bytes.extend_from_slice(&0x1234u64.to_le_bytes());
+        [entry_type, file_ktid, 0xdeadbeef, 0x10000]
+            .iter()
+            .for_each(|x| bytes.extend_from_slice(&x.to_le_bytes()));
+        bytes.extend_from_slice(params);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.resize((bytes.len() + 3) & !3, 0);
+        bytes
+    }
+
+    pub(crate) fn sample_rdb() -> Vec<u8> {
+        let entries = [
+            entry_bytes(0, 0x11111111, &[1, 2, 3, 4, 5, 6, 7, 8], "R_g1t［foo］@1234"),
+            entry_bytes(4, 0x22222222, &[0; 0x18], "R_g1e［bar_baz］@10@20@0"),
+            entry_bytes(0x20, 0x33333333, &[0; 0x28], "R_kts［snd］@10"),
+        ];
+        let mut bytes = Vec::new();
+        let fields = [
+            u32::from_le_bytes(*b"_DRK"),
+            0x30303030,
+            0x30,
+            0,
+            entries.len() as u32,
+            0xcafebabe,
+        ];
+        fields.iter().for_each(|x| bytes.extend_from_slice(&x.to_le_bytes()));
+        bytes.extend_from_slice(b"data/\0");
+        bytes.resize(0x30, 0);
+        entries.iter().for_each(|x| bytes.extend_from_slice(x));
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample_rdb();
+        let rdb = Rdb::from_reader(std::io::Cursor::new(bytes.clone())).unwrap();
+        let mut out = Vec::new();
+        rdb.write(&mut out).unwrap();
+        assert_eq!(out, bytes);
+    }
+
+    #[test]
+    fn add_entry() {
+        let mut rdb = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        let entry = RdbEntry::new_external("new_tex.g1t", EntryType::Texture, 0x1234, 0x40).unwrap();
+        assert_eq!(entry.file_ktid, KTID::new("new_tex.g1t").unwrap().as_u32());
+        assert_eq!(entry.idrk_header_size().unwrap(), 0x58);
+        rdb.add_entry(entry.clone()).unwrap();
+        assert!(rdb.add_entry(entry).is_err());
+
+        let mut bytes = Vec::new();
+        rdb.write(&mut bytes).unwrap();
+        let rdb = Rdb::from_reader(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(rdb.header.file_count, 4);
+        let name = rdb.entries[3].entry_name().unwrap();
+        assert_eq!(name.to_string(), "R_g1t［new_tex］@40");
+    }
+
+    #[test]
+    fn remove_entries() {
+        let mut rdb = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        assert!(rdb.remove_entry_by_ktid(&KTID(0x22222222)).is_some());
+        assert!(rdb.remove_entry_by_ktid(&KTID(0x22222222)).is_none());
+        assert_eq!(rdb.header.file_count, 2);
+
+        let mut bytes = Vec::new();
+        rdb.write(&mut bytes).unwrap();
+        let rdb = Rdb::from_reader(std::io::Cursor::new(bytes)).unwrap();
+        let ktids: Vec<_> = rdb.entries.iter().map(|x| x.file_ktid).collect();
+        assert_eq!(ktids, vec![0x11111111, 0x33333333]);
+    }
+
+    #[test]
+    fn idrk_header_size() {
+        let rdb = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        let sizes: Vec<_> = rdb.entries.iter().map(|x| x.idrk_header_size().ok()).collect();
+        // Known types match their expected size, unknown ones use the size of their block
+        assert_eq!(sizes, vec![Some(0x38), Some(0x48), Some(0x58)]);
+
+        let mut entry = rdb.entries[1].clone();
+        entry.unk_content.truncate(8);
+        assert!(entry.idrk_header_size().is_err());
+    }
+
+    #[test]
+    fn set_external_file() {
+        let dir = std::env::temp_dir().join(format!("rdb_tool_external_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = std::sync::Arc::new(crate::AocConfig::AocConfig::default());
+        let rdb = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap();
+        let data = vec![7u8; 0x100];
+
+        // Already wrapped with LZ4: copied as is, the entry takes its encoding and decompressed size
+        let wrapped = IdrkEntry::wrap(&rdb.entries[0], &data, Compression::Lz4)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let path = dir.join("0x11111111.file");
+        std::fs::write(&path, &wrapped).unwrap();
+        let mut entry = rdb.entries[0].clone();
+        entry.flags.set_internal(true);
+        entry.flags.set_external(false);
+        let buffer = entry
+            .set_external_file(&AocHash::new(&path, config.clone()), Compression::None)
+            .unwrap();
+        assert_eq!(buffer, wrapped);
+        assert_eq!(entry.storage(), Some(RdbStorage::External));
+        assert_eq!(entry.encoding(), RdbEncoding::Lz4);
+        assert_eq!(entry.file_size, 0x100);
+        assert_eq!(entry.entry_name().unwrap().size(), Some(0x100));
+
+        // Raw file: wrapped with the requested compression
+        std::fs::write(&path, &data).unwrap();
+        let buffer = entry.set_external_file(&AocHash::new(&path, config), Compression::Zlib).unwrap();
+        assert_eq!(entry.encoding(), RdbEncoding::Zlib);
+        let idrk = IdrkEntry::from_reader(&mut std::io::Cursor::new(buffer)).unwrap();
+        assert_eq!(idrk.unwrap_data().unwrap(), data);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entry_types() {
+        let types = [
+            (0, EntryType::Plain),
+            (1, EntryType::SingletonDb),
+            (4, EntryType::Effect),
+            (8, EntryType::Texture),
+            (12, EntryType::Model),
+            (2, EntryType::Other(2)),
+            (0x20, EntryType::Other(0x20)),
+        ];
+        for (value, entry_type) in types {
+            assert_eq!(EntryType::from(value), entry_type);
+            assert_eq!(entry_type.as_u32(), value);
+        }
+        assert_eq!(EntryType::Texture.to_string(), "8 (Texture)");
+    }
+
+    #[test]
+    fn malformed_entry() {
+        // Name size of the second entry bigger than the entry itself
+        let mut bytes = sample_rdb();
+        let offset = 0x30 + entry_bytes(0, 0x11111111, &[1, 2, 3, 4, 5, 6, 7, 8], "R_g1t［foo］@1234").len();
+        bytes[offset + 0x10..offset + 0x14].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert!(matches!(
+            Rdb::from_reader(std::io::Cursor::new(bytes)),
+            Err(Error::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn idrk_from_garbage() {
+        let garbage = vec![0xffu8; 0x80];
+        assert!(IdrkEntry::from_reader(&mut std::io::Cursor::new(garbage)).is_err());
+
+        // IDRK header claiming a payload bigger than the whole entry
+        let mut bytes = entry_bytes(0, 0x11111111, &[0; 8], "");
+        bytes[0x10..0x14].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert!(IdrkEntry::from_reader(&mut std::io::Cursor::new(bytes)).is_err());
+
+        let entry = Rdb::from_reader(std::io::Cursor::new(sample_rdb())).unwrap().entries[0].clone();
+        let bytes = IdrkEntry::wrap(&entry, b"payload", Compression::None).unwrap().to_bytes().unwrap();
+        let idrk = IdrkEntry::from_reader(&mut std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(idrk.header_size(), entry.idrk_header_size().unwrap());
+        assert_eq!(idrk.payload(), b"payload");
+    }
+}
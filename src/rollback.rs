@@ -0,0 +1,188 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Context, Error, Result},
+    AocConfig::to_slash_string,
+};
+
+/// Journal of the last merge, in the backup directory
+pub const JOURNAL_FILE: &str = "rollback.json";
+/// Copies of the overwritten and deleted outputs, in the backup directory
+const SAVED_DIR: &str = "files";
+
+/// Backup directory of a merged mod. The `#` prefix keeps the merger from taking it for a mod.
+pub fn backup_dir(merged_dir: &Path) -> PathBuf {
+    let name = merged_dir
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    merged_dir.with_file_name(format!("#{}.backup", name))
+}
+
+/// What a merge did to the merged mod, paths relative to it
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RollbackJournal {
+    /// The merged mod didn't exist before, rolling back removes it
+    pub created_dir: bool,
+    /// Files that didn't exist before the merge
+    pub created: BTreeSet<String>,
+    /// Files overwritten or deleted by the merge, copied to the backup directory first
+    pub saved: BTreeSet<String>,
+}
+
+/// Keeps what's needed to undo a merge while it writes to the merged mod
+pub struct Backup {
+    merged_dir: PathBuf,
+    dir: PathBuf,
+    journal: RefCell<RollbackJournal>,
+    /// The backup of the previous merge has been replaced by this one
+    started: Cell<bool>,
+}
+
+impl Backup {
+    /// Start a new backup. The one of the previous merge is only dropped once something is written,
+    /// so a merge that changes nothing can still roll back the one before it.
+    pub fn start(merged_dir: &Path) -> Result<Self> {
+        Ok(Self {
+            merged_dir: merged_dir.to_path_buf(),
+            dir: backup_dir(merged_dir),
+            journal: RefCell::new(RollbackJournal {
+                created_dir: !merged_dir.exists(),
+                ..Default::default()
+            }),
+            started: Cell::new(false),
+        })
+    }
+
+    /// Replace the backup of the previous merge on the first change
+    fn ensure_started(&self) -> Result<()> {
+        if self.started.get() {
+            return Ok(());
+        }
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).with_path(&self.dir)?;
+        }
+        fs::create_dir_all(&self.dir).with_path(&self.dir)?;
+        self.started.set(true);
+        self.save_journal()
+    }
+
+    /// Call before writing or deleting `path`, a file of the merged mod
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let relative = to_slash_string(path.strip_prefix(&self.merged_dir).map_err(|_| {
+            Error::invalid(format!("{} is outside of {}", path.display(), self.merged_dir.display()))
+        })?);
+
+        {
+            let journal = self.journal.borrow();
+            if journal.created.contains(&relative) || journal.saved.contains(&relative) {
+                return Ok(());
+            }
+        }
+        self.ensure_started()?;
+        let mut journal = self.journal.borrow_mut();
+        if path.is_file() {
+            let saved_path = self.dir.join(SAVED_DIR).join(&relative);
+            if let Some(parent) = saved_path.parent() {
+                fs::create_dir_all(parent).with_path(parent)?;
+            }
+            fs::copy(path, &saved_path).with_path(&saved_path)?;
+            journal.saved.insert(relative);
+        } else {
+            journal.created.insert(relative);
+        }
+        Ok(())
+    }
+
+    /// Write the journal, unless nothing was changed
+    pub fn save_journal(&self) -> Result<()> {
+        if !self.started.get() {
+            return Ok(());
+        }
+        let path = self.dir.join(JOURNAL_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&*self.journal.borrow())?).with_path(path)
+    }
+}
+
+/// Undo the last merge of `merged_dir`: restore the files it overwrote or deleted and remove the ones it created
+pub fn rollback(merged_dir: &Path) -> Result<()> {
+    let dir = backup_dir(merged_dir);
+    let journal_path = dir.join(JOURNAL_FILE);
+    if !journal_path.exists() {
+        return Err(Error::invalid(format!(
+            "Nothing to roll back, {} not found",
+            journal_path.display()
+        )));
+    }
+    let journal: RollbackJournal =
+        serde_json::from_str(&fs::read_to_string(&journal_path).with_path(&journal_path)?)
+            .with_path(&journal_path)?;
+
+    if journal.created_dir {
+        if merged_dir.exists() {
+            fs::remove_dir_all(merged_dir).with_path(merged_dir)?;
+        }
+        println!("Removed {}", merged_dir.display());
+    } else {
+        for relative in journal.created.iter() {
+            let path = merged_dir.join(relative);
+            if path.exists() {
+                fs::remove_file(&path).with_path(&path)?;
+                println!("Removed {}", path.display());
+            }
+        }
+        for relative in journal.saved.iter() {
+            let path = merged_dir.join(relative);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).with_path(parent)?;
+            }
+            fs::copy(dir.join(SAVED_DIR).join(relative), &path).with_path(&path)?;
+            println!("Restored {}", path.display());
+        }
+    }
+
+    fs::remove_dir_all(&dir).with_path(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_and_rollback() {
+        let root = std::env::temp_dir().join(format!("rdb_tool_rollback_{}", std::process::id()));
+        let merged_dir = root.join("merged");
+        fs::create_dir_all(merged_dir.join("data")).unwrap();
+        fs::write(merged_dir.join("a.rdb"), b"old").unwrap();
+        fs::write(merged_dir.join("data/stale.file"), b"stale").unwrap();
+
+        let backup = Backup::start(&merged_dir).unwrap();
+        for (name, data) in [("a.rdb", Some(&b"new"[..])), ("data/new.file", Some(b"new")), ("data/stale.file", None)] {
+            let path = merged_dir.join(name);
+            backup.save(&path).unwrap();
+            match data {
+                Some(data) => fs::write(&path, data).unwrap(),
+                None => fs::remove_file(&path).unwrap(),
+            }
+        }
+        backup.save_journal().unwrap();
+
+        // A merge that changes nothing keeps the backup of the one before
+        Backup::start(&merged_dir).unwrap().save_journal().unwrap();
+
+        rollback(&merged_dir).unwrap();
+        assert_eq!(fs::read(merged_dir.join("a.rdb")).unwrap(), b"old");
+        assert_eq!(fs::read(merged_dir.join("data/stale.file")).unwrap(), b"stale");
+        assert!(!merged_dir.join("data/new.file").exists());
+        assert!(!backup_dir(&merged_dir).exists());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
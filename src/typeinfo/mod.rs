@@ -1,5 +1,3 @@
-#![allow(dead_code)]
-
 pub mod object;
 pub mod rendernode;
 pub mod resource;
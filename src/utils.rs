@@ -1,8 +1,10 @@
-use std::{fs, io};
+use std::fs;
 use std::path::{Path, PathBuf};
 
 use walkdir::WalkDir;
 
+use crate::error::{Context, Error, Result};
+
 pub fn is_yuzu_dir<P: AsRef<Path>>(path: P) -> bool {
     let mut p = PathBuf::from(path.as_ref());
     if !p.is_dir() {
@@ -29,7 +31,7 @@ pub fn is_yuzu_dir<P: AsRef<Path>>(path: P) -> bool {
     } 
 
 
-    return game_id == "01002B00111A2000" && load_dir == "load" && yuzu_dir == "yuzu";
+    game_id == "01002B00111A2000" && load_dir == "load" && yuzu_dir == "yuzu"
 }
 pub fn is_ryu_dir<P: AsRef<Path>>(path: P) -> bool {
     let mut p = PathBuf::from(path.as_ref());
@@ -51,7 +53,7 @@ pub fn is_ryu_dir<P: AsRef<Path>>(path: P) -> bool {
         ryu_dir = filename.to_str().unwrap_or_default().to_string();
     } 
 
-    return game_id == "01002B00111A2000"  && ryu_dir == "Ryujinx";
+    game_id == "01002B00111A2000"  && ryu_dir == "Ryujinx"
 }
 
 pub fn is_emulator_dir<P:AsRef<Path>>(path: P) -> String {
@@ -61,7 +63,42 @@ pub fn is_emulator_dir<P:AsRef<Path>>(path: P) -> String {
     if is_ryu_dir(&path) {
         return "Ryujinx".to_string();
     }
-    return String::new();
+    String::new()
+}
+
+/// Case-insensitive match supporting `*` (any run of characters) and `?` (any single character)
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last star swallow one more character
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+/// In dry-run mode, print the action instead of doing it
+pub(crate) fn skip_in_dry_run(dry_run: bool, action: String) -> bool {
+    if dry_run {
+        println!("[dry-run] would {}", action);
+    }
+    dry_run
 }
 
 pub fn create_dir_no_check<P: AsRef<Path>>(path: P) -> bool{
@@ -76,53 +113,54 @@ pub fn create_dir_no_check<P: AsRef<Path>>(path: P) -> bool{
     res
 }
 
-pub fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> bool {
-    let mut res = true;
-    fs::rename(src, dst).unwrap_or_else(|e| {
-        eprintln!("Error moving file: {}", e);
-        res = false;
-    });
-    res
-}
-
-
-pub fn copy_dirs<P: AsRef<Path>>(src: P, dst: P) -> io::Result<()> {
+pub fn copy_dirs<P: AsRef<Path>>(src: P, dst: P) -> Result<()> {
     if let Some(filename) = src.as_ref().file_name() {
         let dest_path = dst.as_ref().join(filename);
         println!("Copying merged mod directory to: {}", &dest_path.display());
         copy_dir(&src, &dest_path)?;
     } else {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Couldn't find a directory to copy",
-        ));
+        return Err(Error::invalid(format!(
+            "Couldn't find a directory to copy: {}",
+            src.as_ref().display()
+        )));
     }
     Ok(())
 }
 
-fn copy_dir<P: AsRef<Path>, Q: AsRef<Path>>(source_path: P, dest_path: Q) -> io::Result<()> {
+fn copy_dir<P: AsRef<Path>, Q: AsRef<Path>>(source_path: P, dest_path: Q) -> Result<()> {
     let dst = dest_path.as_ref();
     let src = source_path.as_ref();
     if !dst.exists() {
-        fs::create_dir_all(dst)?;
+        fs::create_dir_all(dst).with_path(dst)?;
     }
 
     for entry in WalkDir::new(src) {
-        let entry = entry?;
+        let entry = entry.map_err(std::io::Error::from).with_path(src)?;
         let src_path = entry.path();
-        let rel_path = src_path.strip_prefix(src).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("Couldn't strip prefix: {}", e),
-            )
-        })?;
+        let rel_path = src_path
+            .strip_prefix(src)
+            .map_err(|e| Error::invalid(format!("Couldn't strip prefix: {}", e)))?;
         let dst_path = dst.join(rel_path);
 
         if src_path.is_dir() {
-            fs::create_dir_all(&dst_path)?;
+            fs::create_dir_all(&dst_path).with_path(&dst_path)?;
         } else {
-            fs::copy(&src_path, &dst_path)?;
+            fs::copy(src_path, &dst_path).with_path(src_path)?;
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("logo_*.g1t", "Logo_Nintendo.g1t"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a?c*", "abcdef"));
+        assert!(wildcard_match("*b*b", "abab"));
+        assert!(!wildcard_match("logo_*.g1t", "logo_nintendo.g1m"));
+        assert!(!wildcard_match("a?c", "ac"));
+    }
+}
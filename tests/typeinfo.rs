@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+use rdb_tool::{ktid, typeinfo};
+use serde::Deserialize;
+
+#[test]
+fn typeinfo_ids() {
+    assert_eq!(typeinfo::object::sound::bank::ID, ktid::ktid("TypeInfo::Object::Sound::Bank").unwrap());
+}
+
+#[derive(Debug, Deserialize)]
+struct TypeInfoEntry {
+    typekind: String,
+    #[allow(dead_code)]
+    ktid: String,
+    typename: String,
+}
+
+/// Regenerates `src/typeinfo` from a `typeinfos.csv` dump
+#[test]
+#[ignore]
+fn generate_typeinfos_lmao_gross() {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_path("typeinfos.csv")
+        .unwrap();
+
+    let typeinfos: Vec<String> = rdr
+        .deserialize()
+        .filter_map(|result| {
+            let record: TypeInfoEntry = result.unwrap();
+
+            //dbg!(record);
+            if record.typekind == "TypeInfo" {
+                Some(record.typename)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    for typeinfo in &typeinfos {
+        let path = PathBuf::from(format!("src/{}", &typeinfo.replace("::", "/")).to_lowercase());
+        std::fs::create_dir_all(&path).unwrap();
+
+        let mod_path = path.join("mod.rs");
+        if !mod_path.exists() {
+            std::fs::write(
+                &mod_path,
+                format!(
+                    "use crate::ktid::KTID;\n\npub const ID: KTID = KTID({});",
+                    ktid::ktid(typeinfo).unwrap().as_u32()
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    for typeinfo in &typeinfos {
+        let path = PathBuf::from(format!("src/{}", &typeinfo.replace("::", "/")));
+
+        path.ancestors().for_each(|ancestor| {
+            let dirs: Vec<String> = std::fs::read_dir(ancestor)
+                .unwrap()
+                .filter_map(|dir| {
+                    let dir = dir.unwrap();
+                    if dir.path().is_dir() {
+                        Some(dir.file_name().to_str().unwrap().to_string())
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            let mod_path = ancestor.join("mod.rs");
+
+            if !mod_path.exists() {
+                let mut output = String::new();
+
+                for dir in dirs {
+                    output.push_str(&format!("pub mod {};\n", dir));
+                }
+
+                std::fs::write(&mod_path, &output).unwrap();
+            }
+        });
+    }
+
+    println!(
+        "{:x}",
+        ktid::ktid("TypeInfo::Object::3D::Displayset::TrianglesEx").unwrap().as_u32()
+    )
+}